@@ -0,0 +1,235 @@
+//! State kept by the server for each open document.
+
+use tower_lsp::lsp_types::{Position as LspPosition, TextDocumentContentChangeEvent};
+use tree_sitter::{InputEdit, Point as TreePoint, Tree};
+
+use crate::{file::parse_file, populate_identifiers, IdentifiersMap};
+
+#[derive(Debug, Default)]
+pub struct Document {
+    source: String,
+    tree: Option<Tree>,
+    version: Option<i32>,
+    identifiers: IdentifiersMap,
+}
+
+impl Document {
+    pub fn new(source: String, version: Option<i32>) -> Self {
+        let mut document = Document::default();
+        document.replace_source(source, version);
+        document
+    }
+
+    /// Replaces the whole source, parsing it and collecting its identifiers again.
+    pub fn replace_source(&mut self, source: String, version: Option<i32>) {
+        self.source = source;
+        self.tree = None;
+        self.version = version;
+        self.reparse();
+    }
+
+    /// Applies the changes in order, editing the old tree along the source, so only the edited
+    /// parts of the document are parsed again.
+    pub fn apply_changes(
+        &mut self,
+        changes: Vec<TextDocumentContentChangeEvent>,
+        version: Option<i32>,
+    ) {
+        for change in changes {
+            match change.range {
+                Some(range) => self.apply_edit(range.start, range.end, &change.text),
+                None => {
+                    self.source = change.text;
+                    self.tree = None;
+                }
+            }
+        }
+        self.version = version;
+        self.reparse();
+    }
+
+    fn apply_edit(&mut self, start: LspPosition, end: LspPosition, new_text: &str) {
+        let start_byte = byte_offset(&self.source, start);
+        let old_end_byte = byte_offset(&self.source, end).max(start_byte);
+        let new_end_byte = start_byte + new_text.len();
+
+        let start_position = tree_point(&self.source, start_byte);
+        let old_end_position = tree_point(&self.source, old_end_byte);
+        self.source
+            .replace_range(start_byte..old_end_byte, new_text);
+        let new_end_position = tree_point(&self.source, new_end_byte);
+
+        if let Some(tree) = self.tree.as_mut() {
+            tree.edit(&InputEdit {
+                start_byte,
+                old_end_byte,
+                new_end_byte,
+                start_position,
+                old_end_position,
+                new_end_position,
+            });
+        }
+    }
+
+    fn reparse(&mut self) {
+        self.tree = parse_file(self.source.as_bytes(), self.tree.as_ref());
+        self.identifiers = self
+            .tree
+            .as_ref()
+            .map(|tree| populate_identifiers(self.source.as_bytes(), tree.root_node()))
+            .unwrap_or_default();
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn tree(&self) -> Option<&Tree> {
+        self.tree.as_ref()
+    }
+
+    pub fn version(&self) -> Option<i32> {
+        self.version
+    }
+
+    pub fn identifiers(&self) -> &IdentifiersMap {
+        &self.identifiers
+    }
+}
+
+/// Converts a LSP position, whose character is counted in UTF-16 code units, into a byte offset.
+/// Positions past the end of a line or of the source are clamped.
+pub(crate) fn byte_offset(source: &str, position: LspPosition) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match source[line_start..].find('\n') {
+            Some(newline) => line_start += newline + 1,
+            None => return source.len(),
+        }
+    }
+
+    let line = source[line_start..].split('\n').next().unwrap_or_default();
+    let mut utf16_column = 0;
+    for (byte_idx, c) in line.char_indices() {
+        if utf16_column >= position.character as usize {
+            return line_start + byte_idx;
+        }
+        utf16_column += c.len_utf16();
+    }
+    line_start + line.len()
+}
+
+/// Converts a byte offset into a tree-sitter point, whose column is counted in bytes.
+pub(crate) fn tree_point(source: &str, byte_offset: usize) -> TreePoint {
+    let before = &source.as_bytes()[..byte_offset];
+    let row = before.iter().filter(|&&byte| byte == b'\n').count();
+    let column = before
+        .iter()
+        .rposition(|&byte| byte == b'\n')
+        .map_or(byte_offset, |newline| byte_offset - newline - 1);
+
+    TreePoint { row, column }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::Range as LspRange;
+
+    use super::*;
+
+    /// An emoji takes four bytes but two UTF-16 code units.
+    const SOURCE: &str = "a😀b\nc";
+
+    fn position(line: u32, character: u32) -> LspPosition {
+        LspPosition { line, character }
+    }
+
+    fn change(start: LspPosition, end: LspPosition, text: &str) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: Some(LspRange { start, end }),
+            range_length: None,
+            text: text.to_string(),
+        }
+    }
+
+    fn sexp(document: &Document) -> Option<String> {
+        document.tree().map(|tree| tree.root_node().to_sexp())
+    }
+
+    #[test]
+    fn byte_offset_counts_surrogate_pairs() {
+        assert_eq!(byte_offset(SOURCE, position(0, 1)), 1);
+        assert_eq!(byte_offset(SOURCE, position(0, 3)), 5);
+        assert_eq!(byte_offset(SOURCE, position(1, 0)), 7);
+    }
+
+    #[test]
+    fn byte_offset_never_splits_a_character() {
+        assert_eq!(byte_offset(SOURCE, position(0, 2)), 5);
+    }
+
+    #[test]
+    fn byte_offset_clamps_positions_past_the_end() {
+        assert_eq!(byte_offset(SOURCE, position(0, 4)), 6);
+        assert_eq!(byte_offset(SOURCE, position(0, 40)), 6);
+        assert_eq!(byte_offset(SOURCE, position(1, 40)), SOURCE.len());
+        assert_eq!(byte_offset(SOURCE, position(4, 0)), SOURCE.len());
+    }
+
+    #[test]
+    fn tree_point_counts_bytes() {
+        assert_eq!(tree_point(SOURCE, 0), TreePoint { row: 0, column: 0 });
+        assert_eq!(tree_point(SOURCE, 5), TreePoint { row: 0, column: 5 });
+        assert_eq!(tree_point(SOURCE, 6), TreePoint { row: 0, column: 6 });
+        assert_eq!(tree_point(SOURCE, 8), TreePoint { row: 1, column: 1 });
+    }
+
+    #[test]
+    fn apply_changes_matches_a_full_reparse() {
+        let mut document = Document::new(
+            "Table users {\n  id int [note: '😀']\n}\n".to_string(),
+            Some(1),
+        );
+        document.apply_changes(
+            vec![
+                // Each change is relative to the source left by the previous one
+                change(position(1, 17), position(1, 19), "hi"),
+                change(position(1, 20), position(1, 20), ", pk"),
+                change(position(2, 0), position(2, 0), "  name varchar\n"),
+                change(position(0, 6), position(0, 11), "people"),
+            ],
+            Some(2),
+        );
+
+        let expected = "Table people {\n  id int [note: 'hi', pk]\n  name varchar\n}\n";
+        assert_eq!(document.source(), expected);
+        assert_eq!(document.version(), Some(2));
+        assert_eq!(
+            sexp(&document),
+            sexp(&Document::new(expected.to_string(), None))
+        );
+    }
+
+    #[test]
+    fn apply_changes_replaces_the_whole_source_without_range() {
+        let mut document = Document::new("Table users {\n  id int\n}\n".to_string(), Some(1));
+        document.apply_changes(
+            vec![
+                TextDocumentContentChangeEvent {
+                    range: None,
+                    range_length: None,
+                    text: "Enum status {\n  active\n}\n".to_string(),
+                },
+                change(position(1, 2), position(1, 8), "inactive"),
+            ],
+            Some(2),
+        );
+
+        let expected = "Enum status {\n  inactive\n}\n";
+        assert_eq!(document.source(), expected);
+        assert_eq!(
+            sexp(&document),
+            sexp(&Document::new(expected.to_string(), None))
+        );
+    }
+}
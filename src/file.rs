@@ -1,7 +1,8 @@
 use std::{
     ffi::OsString,
-    fs::{File, OpenOptions},
+    fs::{self, File, OpenOptions},
     io::{BufReader, Read},
+    path::{Path, PathBuf},
 };
 
 use anyhow::Result;
@@ -46,7 +47,45 @@ pub fn parse_file<T: AsRef<[u8]>>(file: T, old_tree: Option<&Tree>) -> Option<Tr
 fn read_from_disk(file_path: OsString) -> Result<File> {
     OpenOptions::new()
         .read(true)
-        .write(true)
         .open(file_path)
         .map_err(|e| e.into())
 }
+
+/// Directories which never hold schemas worth indexing.
+const IGNORED_DIRECTORIES: &[&str] = &["node_modules", "target"];
+
+/// Recursively lists every `.dbml` file inside the folder, skipping hidden directories.
+pub fn find_dbml_files(folder: &Path) -> Vec<PathBuf> {
+    let mut found = vec![];
+    let entries = match fs::read_dir(folder) {
+        Ok(entries) => entries,
+        Err(_) => return found,
+    };
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        // Symbolic links are not followed, so we can't loop forever
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(_) => continue,
+        };
+
+        if file_type.is_dir() {
+            let is_ignored = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map_or(true, |name| {
+                    name.starts_with('.') || IGNORED_DIRECTORIES.contains(&name)
+                });
+            if !is_ignored {
+                found.extend(find_dbml_files(&path));
+            }
+        } else if path
+            .extension()
+            .map_or(false, |extension| extension == "dbml")
+        {
+            found.push(path);
+        }
+    }
+    found
+}
@@ -0,0 +1,208 @@
+//! Tells code apart from comments and quoted text, so that braces, brackets and separators are
+//! only taken into account where they mean something. Unlike the tree, this also works on text
+//! which is still being written.
+
+/// What a byte of the text belongs to.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum Lexeme {
+    Code,
+    LineComment,
+    BlockComment,
+    /// Text between the given quotes, the quotes included
+    Quoted(&'static str),
+}
+
+/// Openings of comments and quoted text, longest first.
+const OPENINGS: &[(&str, Lexeme)] = &[
+    ("//", Lexeme::LineComment),
+    ("/*", Lexeme::BlockComment),
+    ("'''", Lexeme::Quoted("'''")),
+    ("'", Lexeme::Quoted("'")),
+    ("\"", Lexeme::Quoted("\"")),
+    ("`", Lexeme::Quoted("`")),
+];
+
+/// Lexeme of every byte of the text, along with the one still open at its end.
+fn lex(text: &str) -> (Vec<Lexeme>, Lexeme) {
+    let bytes = text.as_bytes();
+    let mut lexemes = Vec::with_capacity(bytes.len());
+    let mut lexeme = Lexeme::Code;
+    let mut escaped = false;
+    let mut idx = 0;
+
+    while idx < bytes.len() {
+        let rest = &bytes[idx..];
+
+        let delimiter = match lexeme {
+            Lexeme::Code => OPENINGS
+                .iter()
+                .find(|(opening, _)| rest.starts_with(opening.as_bytes()))
+                .map(|(opening, opened)| {
+                    lexeme = *opened;
+                    (opening.len(), *opened)
+                }),
+            // The newline is not part of the comment
+            Lexeme::LineComment if bytes[idx] == b'\n' => {
+                lexeme = Lexeme::Code;
+                None
+            }
+            Lexeme::BlockComment if rest.starts_with(b"*/") => {
+                lexeme = Lexeme::Code;
+                Some((2, Lexeme::BlockComment))
+            }
+            Lexeme::Quoted(_) if escaped => {
+                escaped = false;
+                None
+            }
+            Lexeme::Quoted(_) if bytes[idx] == b'\\' => {
+                escaped = true;
+                None
+            }
+            Lexeme::Quoted(closing) if rest.starts_with(closing.as_bytes()) => {
+                lexeme = Lexeme::Code;
+                Some((closing.len(), Lexeme::Quoted(closing)))
+            }
+            _ => None,
+        };
+
+        match delimiter {
+            Some((length, delimited)) => {
+                lexemes.extend((0..length).map(|_| delimited));
+                idx += length;
+            }
+            None => {
+                lexemes.push(lexeme);
+                idx += 1;
+            }
+        }
+    }
+    (lexemes, lexeme)
+}
+
+/// Lexeme of every byte of the text, delimiters included.
+pub(crate) fn lexemes(text: &str) -> Vec<Lexeme> {
+    lex(text).0
+}
+
+/// Lexeme still open at the end of the text, such as a string being written.
+pub(crate) fn open_lexeme(text: &str) -> Lexeme {
+    lex(text).1
+}
+
+/// Bytes of the text which are code, along with their offsets.
+pub(crate) fn code_bytes(text: &str) -> Vec<(usize, u8)> {
+    text.bytes()
+        .zip(lexemes(text))
+        .enumerate()
+        .filter(|(_, (_, lexeme))| *lexeme == Lexeme::Code)
+        .map(|(idx, (byte, _))| (idx, byte))
+        .collect()
+}
+
+/// Offset of the last byte of code equal to the given one.
+pub(crate) fn rfind_code(text: &str, byte: u8) -> Option<usize> {
+    code_bytes(text)
+        .into_iter()
+        .rev()
+        .find(|(_, code_byte)| *code_byte == byte)
+        .map(|(idx, _)| idx)
+}
+
+/// Splits the text on the separator, except where it is quoted or commented out.
+pub(crate) fn split_code(text: &str, separator: u8) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut part_start = 0;
+
+    for (idx, _) in code_bytes(text)
+        .into_iter()
+        .filter(|(_, byte)| *byte == separator)
+    {
+        parts.push(&text[part_start..idx]);
+        part_start = idx + 1;
+    }
+    parts.push(&text[part_start..]);
+    parts
+}
+
+/// The text with its comments blanked out, so that offsets into it still match the original.
+pub(crate) fn without_comments(text: &str) -> String {
+    let bytes = text
+        .bytes()
+        .zip(lexemes(text))
+        .map(|(byte, lexeme)| match lexeme {
+            Lexeme::LineComment | Lexeme::BlockComment if byte != b'\n' => b' ',
+            _ => byte,
+        })
+        .collect::<Vec<_>>();
+
+    // Comments are blanked as a whole, so no character is left split
+    String::from_utf8(bytes).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lex_keeps_escaped_quotes_inside_strings() {
+        assert_eq!(open_lexeme(r"note: 'it\'s"), Lexeme::Quoted("'"));
+        assert_eq!(open_lexeme(r"note: 'it\'s'"), Lexeme::Code);
+        assert_eq!(open_lexeme(r#"note: "a\\""#), Lexeme::Code);
+    }
+
+    #[test]
+    fn lex_keeps_single_quotes_inside_triple_quoted_strings() {
+        let text = "note: '''\n  it's 'quoted'\n";
+        assert_eq!(open_lexeme(text), Lexeme::Quoted("'''"));
+        assert_eq!(open_lexeme(&format!("{}'''", text)), Lexeme::Code);
+        assert!(lexemes(text)[6..]
+            .iter()
+            .all(|lexeme| *lexeme == Lexeme::Quoted("'''")));
+    }
+
+    #[test]
+    fn lex_ends_line_comments_before_the_newline() {
+        assert_eq!(
+            lexemes("a //\nb"),
+            vec![
+                Lexeme::Code,
+                Lexeme::Code,
+                Lexeme::LineComment,
+                Lexeme::LineComment,
+                Lexeme::Code,
+                Lexeme::Code,
+            ]
+        );
+        assert_eq!(open_lexeme("a /* b\n"), Lexeme::BlockComment);
+    }
+
+    #[test]
+    fn split_code_skips_quoted_and_commented_separators() {
+        assert_eq!(
+            split_code("a, 'b, c', \"d,\" // e, f", b','),
+            vec!["a", " 'b, c'", " \"d,\" // e, f"]
+        );
+        assert_eq!(split_code("x /* , */, y", b','), vec!["x /* , */", " y"]);
+        assert_eq!(split_code("", b','), vec![""]);
+    }
+
+    #[test]
+    fn rfind_code_skips_comments() {
+        assert_eq!(rfind_code("a.b // c.d", b'.'), Some(1));
+        assert_eq!(rfind_code("'a.b'", b'.'), None);
+    }
+
+    #[test]
+    fn without_comments_keeps_offsets() {
+        let comment = "// pk, [ref: > a.b] {é";
+        assert_eq!(
+            without_comments(&format!("id int {}\nname", comment)),
+            format!("id int {}\nname", " ".repeat(comment.len()))
+        );
+        assert_eq!(
+            without_comments("a /* {\n[ */ b"),
+            "a     \n     b".to_string()
+        );
+        assert_eq!(without_comments("'// kept'"), "'// kept'".to_string());
+    }
+}
@@ -11,25 +11,33 @@
 
 //! DBML is a very simple language with a single document structure, with no imports at all.
 //!
-//! With this in mind, each request parses the whole document, since there is no need for the
-//! additional complexity of storing old document trees.
+//! Each open document keeps its tree, which is edited along every change and then handed back to
+//! tree-sitter, so only the edited parts of large schemas are parsed again.
 
 use std::{collections::HashMap, sync::Mutex};
 
-use log::info;
+use log::{debug, info};
 use once_cell::sync::Lazy;
 use tower_lsp::lsp_types::Position as LspPosition;
 use tree_sitter::{Language, Node, Parser, Query, QueryCapture, QueryCursor};
 
 use crate::{
-    navigation::node_parent_identifier,
+    document::{byte_offset, tree_point},
+    lexer::{code_bytes, open_lexeme, rfind_code, split_code, without_comments, Lexeme},
+    navigation::{
+        enum_values, field_settings, node_parent_identifier, note_text, parent_of_kind, table_note,
+    },
+    relationships::CARDINALITY_OPERATORS,
     wrappers::{Point, Range},
 };
 use navigation::search_valid_node;
 
+pub mod document;
 pub mod file;
+pub mod lexer;
 pub mod navigation;
 pub mod providers;
+pub mod relationships;
 pub mod wrappers;
 
 extern "C" {
@@ -47,7 +55,13 @@ pub static PARSER: Lazy<Mutex<Parser>> = Lazy::new(|| {
 #[derive(Debug, Default)]
 pub struct IdentifiersMap {
     tables_with_fields: HashMap<String, Vec<FieldInfo>>,
+    /// Table names and aliases, pointing to the table definition
+    table_definitions: HashMap<String, TableInfo>,
+    /// Aliases, pointing to the name of the table they stand for
+    table_aliases: HashMap<String, String>,
     enums_without_discriminants: Vec<String>,
+    /// Enum names, pointing to the enum definition
+    enum_definitions: HashMap<String, EnumInfo>,
 }
 
 impl IdentifiersMap {
@@ -63,6 +77,62 @@ impl IdentifiersMap {
     fn fields_of_table(&self, table_name: &str) -> Option<Vec<FieldInfo>> {
         Some(self.tables_with_fields.get_key_value(table_name)?.1.clone())
     }
+
+    fn field_of_table(&self, table_name: &str, field_name: &str) -> Option<&FieldInfo> {
+        self.tables_with_fields
+            .get(table_name)?
+            .iter()
+            .find(|field| field.text_name == field_name)
+    }
+
+    /// Resolves aliases into the name of the table they stand for. A name taken both by a table
+    /// and by the alias of another one stands for whichever was defined first.
+    fn table_name_of(&self, table_or_alias: &str) -> String {
+        self.table_info(table_or_alias).map_or_else(
+            || table_or_alias.to_string(),
+            |table| table.text_name.clone(),
+        )
+    }
+
+    /// Every name a table can be referenced by, that is, its own name and its aliases.
+    fn names_of_table(&self, table_name: &str) -> Vec<String> {
+        let aliases = self
+            .table_aliases
+            .iter()
+            .filter(|(alias, table)| {
+                table.as_str() == table_name && self.table_name_of(alias) == table_name
+            })
+            .map(|(alias, _)| alias.to_string());
+
+        std::iter::once(table_name.to_string())
+            .chain(aliases)
+            .collect()
+    }
+
+    fn table_definition(&self, table_name: &str) -> Option<Range> {
+        self.table_info(table_name).map(|table| table.range)
+    }
+
+    fn table_info(&self, table_name: &str) -> Option<&TableInfo> {
+        self.table_definitions.get(table_name)
+    }
+
+    fn enum_definition(&self, enum_name: &str) -> Option<Range> {
+        self.enum_info(enum_name).map(|enum_info| enum_info.range)
+    }
+
+    fn enum_info(&self, enum_name: &str) -> Option<&EnumInfo> {
+        self.enum_definitions.get(enum_name)
+    }
+}
+
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+struct TableInfo {
+    text_name: String,
+    alias: Option<String>,
+    note: Option<String>,
+    /// Position range which the table name is defined
+    range: Range,
 }
 
 #[derive(Debug, Default, Eq, PartialEq, Clone)]
@@ -70,14 +140,28 @@ struct FieldInfo {
     text_name: String,
     /// Field's type
     r#type: String,
+    /// Settings such as `pk` or `default: 0`, as written, except for the note
+    settings: Vec<String>,
+    note: Option<String>,
     /// Position range which fields are defined
     range: Range,
 }
 
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+struct EnumInfo {
+    text_name: String,
+    values: Vec<String>,
+    /// Position range which the enum name is defined
+    range: Range,
+}
+
 pub fn populate_identifiers<'a>(source: &'a [u8], root_node: Node<'a>) -> IdentifiersMap {
     let mut identifiers_map = IdentifiersMap {
         tables_with_fields: Default::default(),
+        table_definitions: Default::default(),
+        table_aliases: Default::default(),
         enums_without_discriminants: vec![],
+        enum_definitions: Default::default(),
     };
 
     populate_table_identifiers(source, root_node, &mut identifiers_map);
@@ -101,6 +185,25 @@ fn populate_enum_identifiers(
         .map(|node| node.utf8_text(source).unwrap().to_string())
         .collect();
 
+    out_identifiers_map.enum_definitions = enum_nodes
+        .iter()
+        .map(|node| {
+            let enum_name = node.utf8_text(source).unwrap().to_string();
+            let values = node
+                .parent()
+                .map(enum_values)
+                .unwrap_or_default()
+                .iter()
+                .map(|value| value.utf8_text(source).unwrap().to_string())
+                .collect();
+            let info = EnumInfo {
+                text_name: enum_name.clone(),
+                values,
+                range: Range::from_tree(source, node.range()),
+            };
+            (enum_name, info)
+        })
+        .collect();
     out_identifiers_map.enums_without_discriminants = enum_identifiers;
 }
 
@@ -109,6 +212,43 @@ fn populate_table_identifiers(
     root_node: Node,
     out_identifiers_map: &mut IdentifiersMap,
 ) {
+    let tables_query =
+        Query::new(*LANGUAGE, r#"(table_definition name: (identifier) @name )"#).unwrap();
+
+    let mut query_table_cursor = QueryCursor::new();
+    let table_nodes = query_for_nodes(&mut query_table_cursor, source, root_node, &tables_query);
+
+    for node in table_nodes {
+        let table_name = node.utf8_text(source).unwrap().to_string();
+        let table_definition = node.parent().unwrap();
+        let table_alias = table_definition
+            .child_by_field_name("alias")
+            .map(|alias| alias.utf8_text(source).unwrap().to_string());
+        let info = TableInfo {
+            text_name: table_name.clone(),
+            alias: table_alias.clone(),
+            note: table_note(source, table_definition),
+            range: Range::from_tree(source, node.range()),
+        };
+
+        if let Some(alias) = table_alias {
+            out_identifiers_map
+                .table_aliases
+                .entry(alias.clone())
+                .or_insert_with(|| table_name.clone());
+            out_identifiers_map
+                .table_definitions
+                .entry(alias)
+                .or_insert_with(|| info.clone());
+        }
+
+        // Duplicated tables are reported on diagnostics, the first definition is the one kept
+        out_identifiers_map
+            .table_definitions
+            .entry(table_name)
+            .or_insert(info);
+    }
+
     let fields_query = Query::new(
         *LANGUAGE,
         r#"(table_definition name: (identifier)
@@ -123,13 +263,32 @@ fn populate_table_identifiers(
     for node in field_nodes {
         let table_name = node_parent_identifier(source, &node, "table_definition", "name").unwrap();
         let table_alias = node_parent_identifier(source, &node, "table_definition", "alias");
+        let table_range = parent_of_kind(node, "table_definition")
+            .and_then(|table| table.child_by_field_name("name"))
+            .map(|name| Range::from_tree(source, name.range()));
+        // Like definitions, names taken by a previous table keep that table's fields
+        let owns_name = |name: &str| {
+            out_identifiers_map
+                .table_definitions
+                .get(name)
+                .map(|table| table.range)
+                == table_range
+        };
+        let table_alias = table_alias.filter(|alias| owns_name(alias));
+        let owns_table_name = owns_name(&table_name);
         let field_range = node.range();
         let field_name = node.utf8_text(source).unwrap();
         let field_type = node.next_sibling().unwrap().utf8_text(source).unwrap();
+        let (note, settings): (Vec<String>, Vec<String>) =
+            field_settings(source, node.parent().unwrap())
+                .into_iter()
+                .partition(|setting| setting.to_lowercase().starts_with("note"));
         let info = FieldInfo {
             text_name: field_name.to_string(),
             r#type: field_type.to_string(),
-            range: field_range.into(),
+            settings,
+            note: note.first().map(|note| note_text(note)),
+            range: Range::from_tree(source, field_range),
         };
 
         if let Some(alias) = table_alias {
@@ -140,11 +299,13 @@ fn populate_table_identifiers(
                 .or_insert_with(|| vec![info.clone()]);
         }
 
-        out_identifiers_map
-            .tables_with_fields
-            .entry(table_name)
-            .and_modify(|e| e.push(info.clone()))
-            .or_insert_with(|| vec![info]);
+        if owns_table_name {
+            out_identifiers_map
+                .tables_with_fields
+                .entry(table_name)
+                .and_modify(|e| e.push(info.clone()))
+                .or_insert_with(|| vec![info]);
+        }
     }
 }
 
@@ -173,7 +334,33 @@ fn find_location_on_ast(
     root_node: Node,
     edit_position: LspPosition,
 ) -> CursorLocation {
-    let current_pos = Point::from(edit_position);
+    // The position counts UTF-16 code units, while the tree counts bytes
+    let text = String::from_utf8_lossy(source);
+    let cursor = byte_offset(&text, edit_position);
+    let current_pos = Point(tree_point(&text, cursor));
+
+    // Nothing is completed on comments and strings, but backtick expressions hold columns
+    match open_lexeme(&text[..cursor]) {
+        Lexeme::Code | Lexeme::Quoted("`") => {}
+        _ => return CursorLocation::Unknown,
+    }
+
+    // Settings are still being written, so the tree is usually broken around them
+    if let Some(location) = index_location(source, edit_position) {
+        return location;
+    }
+    if let Some(location) = relationship_setting_location(&line_prefix(source, edit_position)) {
+        return location;
+    }
+    if let Some(location) = setting_list_location(&line_prefix(source, edit_position)) {
+        return location;
+    }
+    if let Some(location) = column_tuple_location(source, edit_position) {
+        return location;
+    }
+    if is_top_level_word(source, edit_position) {
+        return CursorLocation::TopLevel;
+    }
 
     let current_node = root_node
         .named_descendant_for_point_range(*current_pos, *current_pos)
@@ -181,27 +368,24 @@ fn find_location_on_ast(
     let current_node_kind = current_node.kind();
     let parent_kind = current_node.parent().map(|c| c.kind());
 
-    println!("root node: {:?}", root_node);
-    println!("current_node: {:?}", current_pos);
-    println!(
+    debug!("root node: {:?}", root_node);
+    debug!("current_node: {:?}", current_pos);
+    debug!(
         "current_node: {:?}, {:?}",
         current_node.utf8_text(source),
         current_node
     );
-    println!("prev sibling: {:?}", current_node.prev_sibling());
-    println!(
+    debug!("prev sibling: {:?}", current_node.prev_sibling());
+    debug!(
         "prev sibling: {:?}",
         current_node.prev_sibling().map(|c| c.utf8_text(source))
     );
-    println!(
+    debug!(
         "parent name: {:?}, kind: {:?}",
         current_node.parent().map(|c| c.utf8_text(source)),
         current_node.parent().map(|c| c.kind())
     );
 
-    println!("is error?, {:?}", root_node.is_error());
-    println!("is missing?, {:?}", root_node.is_missing());
-
     if let "field_attribute_list" = current_node_kind {
         let new_node = search_valid_node(current_pos, root_node, "field_declaration_list");
         info!("{:?}", new_node);
@@ -218,24 +402,24 @@ fn find_location_on_ast(
         return CursorLocation::Unknown;
     }
 
-    println!("---- Searching for valid node.. ----");
+    debug!("---- Searching for valid node.. ----");
 
     let current_node = search_valid_node(current_pos, root_node, "project_file");
     let current_node_kind = current_node.map(|c| c.kind());
     let parent_kind = current_node.map(|c| c.parent()).flatten().map(|c| c.kind());
 
-    println!("current_node: {:?}", current_pos);
-    println!(
+    debug!("current_node: {:?}", current_pos);
+    debug!(
         "current_node: {:?}, {:?}",
         current_node.map(|c| c.utf8_text(source)),
         current_node
     );
-    println!("prev sibling: {:?}", current_node.map(|c| c.prev_sibling()));
-    println!(
+    debug!("prev sibling: {:?}", current_node.map(|c| c.prev_sibling()));
+    debug!(
         "prev sibling: {:?}",
         current_node.map(|c| c.prev_sibling().map(|c| c.utf8_text(source)))
     );
-    println!(
+    debug!(
         "parent name: {:?}, kind: {:?}",
         current_node.map(|c| c.parent().map(|c| c.utf8_text(source))),
         current_node.map(|c| c.parent().map(|c| c.kind()))
@@ -249,14 +433,366 @@ fn find_location_on_ast(
         }
         if node_text == "." {
             let table = node.prev_sibling().unwrap().utf8_text(source).unwrap();
-            println!("table_name from tablefield {:?}", table);
-            return CursorLocation::TableField_Field(table.to_string());
+            debug!("table_name from tablefield {:?}", table);
+            return CursorLocation::TableField_Field(table.to_string(), ColumnTuple::default());
         }
     }
 
     CursorLocation::Unknown
 }
 
+/// Text of the whole line.
+fn line_text(source: &[u8], row: usize) -> String {
+    let line = source
+        .split(|byte| *byte == b'\n')
+        .nth(row)
+        .unwrap_or_default();
+    String::from_utf8_lossy(line).to_string()
+}
+
+/// Text of the line before the cursor.
+fn line_prefix(source: &[u8], edit_position: LspPosition) -> String {
+    let source = String::from_utf8_lossy(source);
+    let before_cursor = &source[..byte_offset(&source, edit_position)];
+    let line_start = before_cursor.rfind('\n').map_or(0, |newline| newline + 1);
+    before_cursor[line_start..].to_string()
+}
+
+/// Headers of the blocks enclosing the cursor, innermost first, as in `Indexes` and
+/// `Table users as U`.
+fn enclosing_block_headers(source: &[u8], edit_position: LspPosition) -> Vec<String> {
+    let source = String::from_utf8_lossy(source);
+    let before_cursor = &source[..byte_offset(&source, edit_position)];
+    let uncommented = without_comments(before_cursor);
+
+    // Braces on comments and strings, such as notes, don't open nor close blocks
+    let mut open_blocks = vec![];
+    let mut header_start = 0;
+    for (idx, byte) in code_bytes(before_cursor) {
+        match byte {
+            b'{' => {
+                open_blocks.push(uncommented[header_start..idx].trim().to_string());
+                header_start = idx + 1;
+            }
+            b'}' => {
+                let _ = open_blocks.pop();
+                header_start = idx + 1;
+            }
+            b'\n' => header_start = idx + 1,
+            _ => {}
+        }
+    }
+
+    open_blocks.reverse();
+    open_blocks
+}
+
+/// Name of the table declared by a block header, as in `Table users as U`.
+fn table_of_header(header: &str) -> Option<String> {
+    let mut words = header.split_whitespace();
+    if !words.next()?.eq_ignore_ascii_case("table") {
+        return None;
+    }
+    Some(words.next()?.trim_matches('"').to_string())
+}
+
+/// Text before and after a `[` which is not closed yet, that is, of a setting list being written.
+fn open_setting_list(line_prefix: &str) -> Option<(&str, &str)> {
+    let list_start = rfind_code(line_prefix, b'[')?;
+    let settings = &line_prefix[list_start + 1..];
+    if rfind_code(settings, b']').is_some() {
+        return None;
+    }
+    Some((line_prefix[..list_start].trim(), settings))
+}
+
+/// Whether the text is a standalone relationship, either after `Ref:` or inside a `Ref { }`
+/// block, as in `orders.user_id > users.id`.
+fn is_relationship(text: &str) -> bool {
+    let first_word = text
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .next()
+        .unwrap_or_default();
+
+    first_word.eq_ignore_ascii_case("ref")
+        || written_relationship(text)
+            .map_or(false, |(left, _, right)| left.is_some() && right.is_some())
+}
+
+/// Text of the setting list being written on a column, as in `user_id int [pk, ref: >`.
+fn open_field_setting_list(line_prefix: &str) -> Option<&str> {
+    let (before_list, settings) = open_setting_list(line_prefix)?;
+
+    // Index and relationship settings are told apart by what comes before their list
+    let is_column = !before_list.starts_with(|c| c == '(' || c == '`')
+        && !is_relationship(before_list)
+        && !before_list
+            .split_whitespace()
+            .any(|word| CARDINALITY_OPERATORS.contains(&word));
+    if is_column {
+        Some(settings)
+    } else {
+        None
+    }
+}
+
+/// Finds out which part of a relationship setting list is being written.
+fn relationship_setting_location(line_prefix: &str) -> Option<CursorLocation> {
+    let (before_list, settings) = open_setting_list(line_prefix)?;
+    if !is_relationship(before_list) {
+        return None;
+    }
+
+    let (written, current_setting) = split_settings(settings);
+    let current_key = current_setting.to_lowercase();
+    if current_key.starts_with("delete:") || current_key.starts_with("update:") {
+        return Some(CursorLocation::RelationshipSettings_Action);
+    }
+    Some(CursorLocation::RelationshipSettings_Remaining(written))
+}
+
+/// Finds out which part of a column setting list is being written.
+fn setting_list_location(line_prefix: &str) -> Option<CursorLocation> {
+    let settings = open_field_setting_list(line_prefix)?;
+    let (written, current_setting) = split_settings(settings);
+
+    if current_setting.to_lowercase().starts_with("default:") {
+        // The column is declared as `name type [`, so its type is the last word before the list
+        let (before_list, _) = open_setting_list(line_prefix)?;
+        let words = before_list.split_whitespace().collect::<Vec<_>>();
+        if words.len() < 2 {
+            return None;
+        }
+        let field_type = words[words.len() - 1].trim_matches('"').to_string();
+        return Some(CursorLocation::FieldAttributeList_Default(field_type));
+    }
+    if !current_setting.to_lowercase().starts_with("ref:") {
+        return Some(CursorLocation::FieldAttributeList_Remaining(written));
+    }
+
+    let target = current_setting[4..].trim_start();
+    let operator = CARDINALITY_OPERATORS
+        .iter()
+        .find(|operator| target.starts_with(*operator));
+    let target = match operator {
+        Some(operator) => target[operator.len()..].trim_start(),
+        None => return Some(CursorLocation::InlineRef_Operator),
+    };
+
+    Some(match target.find('.') {
+        Some(dot) => CursorLocation::InlineRef_Field(target[..dot].to_string()),
+        None => CursorLocation::InlineRef_Table,
+    })
+}
+
+/// Splits a setting list being written into the settings already written and the one under the
+/// cursor. Commas on quoted values, as in `note: 'a, b'`, don't split settings.
+fn split_settings(settings: &str) -> (Vec<String>, String) {
+    let mut written = split_code(settings, b',')
+        .into_iter()
+        .map(|setting| setting.trim().to_string())
+        .collect::<Vec<_>>();
+    let current_setting = written.pop().unwrap_or_default();
+    (written, current_setting)
+}
+
+/// Whether a single word is being written outside of any block, such as the start of `Table`.
+fn is_top_level_word(source: &[u8], edit_position: LspPosition) -> bool {
+    let line_prefix = line_prefix(source, edit_position);
+    line_prefix
+        .trim_start()
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '_')
+        && enclosing_block_headers(source, edit_position).is_empty()
+}
+
+/// Finds out what is being written on the `Indexes` block of a table: a column, possibly inside a
+/// backtick expression, or a setting.
+fn index_location(source: &[u8], edit_position: LspPosition) -> Option<CursorLocation> {
+    let headers = enclosing_block_headers(source, edit_position);
+    if !headers.first()?.eq_ignore_ascii_case("indexes") {
+        return None;
+    }
+    let line_prefix = line_prefix(source, edit_position);
+
+    if let Some(list_start) = rfind_code(&line_prefix, b'[') {
+        let settings = &line_prefix[list_start + 1..];
+        if rfind_code(settings, b']').is_none() {
+            let (written, current_setting) = split_settings(settings);
+            if current_setting.to_lowercase().starts_with("type:") {
+                return Some(CursorLocation::IndexSettings_Type);
+            }
+            return Some(CursorLocation::IndexSettings_Remaining(written));
+        }
+    }
+
+    // Composite indexes are handled along composite relationships
+    let inside_expression = open_lexeme(&line_prefix) == Lexeme::Quoted("`");
+    let code = code_bytes(&line_prefix);
+    let count_of = |wanted: u8| code.iter().filter(|(_, byte)| *byte == wanted).count();
+    let inside_tuple = count_of(b'(') > count_of(b')');
+    if inside_tuple && !inside_expression {
+        return None;
+    }
+
+    let table = headers.iter().find_map(|header| table_of_header(header))?;
+    Some(CursorLocation::Index_Column(table))
+}
+
+/// Finds out which parenthesised tuple of columns is being written, either on a composite
+/// relationship, as in `Ref: orders.(a, b) > items.(`, or on a composite index, as in `(`.
+fn column_tuple_location(source: &[u8], edit_position: LspPosition) -> Option<CursorLocation> {
+    let line_prefix = line_prefix(source, edit_position);
+    let tuple_start = rfind_code(&line_prefix, b'(')?;
+    let listed_text = &line_prefix[tuple_start + 1..];
+    if rfind_code(listed_text, b')').is_some() || open_lexeme(listed_text) != Lexeme::Code {
+        return None;
+    }
+
+    // The last column is the one being written
+    let mut listed = split_code(listed_text, b',')
+        .into_iter()
+        .map(|column| column.trim().to_string())
+        .collect::<Vec<_>>();
+    let _ = listed.pop();
+    listed.retain(|column| !column.is_empty());
+
+    let before_tuple = line_prefix[..tuple_start].trim_end();
+    if before_tuple.is_empty() {
+        let headers = enclosing_block_headers(source, edit_position);
+        let is_index = headers
+            .first()
+            .map_or(false, |header| header.eq_ignore_ascii_case("indexes"));
+        if !is_index {
+            return None;
+        }
+
+        let table = headers.iter().find_map(|header| table_of_header(header))?;
+        return Some(CursorLocation::Index_Columns(table, listed));
+    }
+
+    if !before_tuple.ends_with('.') {
+        return None;
+    }
+    let before_dot = &before_tuple[..before_tuple.len() - 1];
+    let table_start = before_dot
+        .char_indices()
+        .rev()
+        .find(|(_, c)| !(c.is_alphanumeric() || *c == '_' || *c == '"'))
+        .map_or(0, |(idx, c)| idx + c.len_utf8());
+    let table = before_dot[table_start..].trim_matches('"').to_string();
+    if table.is_empty() {
+        return None;
+    }
+
+    let line = line_text(source, edit_position.line as usize);
+    let opposite = written_relationship(&line).and_then(|(left, operator_idx, right)| {
+        let opposite = if tuple_start < operator_idx {
+            right
+        } else {
+            left
+        };
+        opposite.filter(|side| side.complete)
+    });
+
+    Some(CursorLocation::TableField_Field(
+        table,
+        ColumnTuple {
+            listed,
+            opposite: opposite.map(|side| (side.table, side.columns)),
+        },
+    ))
+}
+
+/// A side of a relationship as written on the line, as in `orders.(id, user_id)`.
+#[derive(Debug, Clone)]
+struct WrittenSide {
+    table: String,
+    columns: Vec<String>,
+    /// Whether every column is written, that is, the tuple is closed
+    complete: bool,
+}
+
+/// Splits a relationship line on its operator, returning both sides and where the operator is.
+fn written_relationship(line: &str) -> Option<(Option<WrittenSide>, usize, Option<WrittenSide>)> {
+    let line = without_comments(line);
+    let code = code_bytes(&line);
+
+    let mut depth = 0_usize;
+    let operator_idx = code.iter().find_map(|(idx, byte)| match byte {
+        b'(' => {
+            depth += 1;
+            None
+        }
+        b')' => {
+            depth = depth.saturating_sub(1);
+            None
+        }
+        b'<' | b'>' | b'-' if depth == 0 => Some(*idx),
+        _ => None,
+    })?;
+    let operator_len = if line[operator_idx..].starts_with("<>") {
+        2
+    } else {
+        1
+    };
+
+    let left = &line[..operator_idx];
+    let right_start = operator_idx + operator_len;
+    // Settings of the relationship are not part of its right side
+    let right_end = code
+        .iter()
+        .find(|(idx, byte)| *idx >= right_start && *byte == b'[')
+        .map_or(line.len(), |(idx, _)| *idx);
+    let right = &line[right_start..right_end];
+    Some((written_side(left), operator_idx, written_side(right)))
+}
+
+fn written_side(text: &str) -> Option<WrittenSide> {
+    // Drops the `Ref name:` prefix of the left side
+    let text = text[rfind_code(text, b':').map_or(0, |colon| colon + 1)..].trim();
+    let (dot, _) = code_bytes(text)
+        .into_iter()
+        .find(|(_, byte)| *byte == b'.')?;
+    let table = text[..dot].trim().trim_matches('"').to_string();
+    let columns_text = text[dot + 1..].trim();
+
+    let (columns, complete) = if columns_text.starts_with('(') {
+        let tuple_end = columns_text.find(')');
+        let columns = split_code(
+            &columns_text[1..tuple_end.unwrap_or(columns_text.len())],
+            b',',
+        )
+        .into_iter()
+        .map(|column| column.trim().to_string())
+        .filter(|column| !column.is_empty())
+        .collect::<Vec<_>>();
+        (columns, tuple_end.is_some())
+    } else {
+        let columns = columns_text
+            .split_whitespace()
+            .next()
+            .map(|column| vec![column.to_string()])
+            .unwrap_or_default();
+        let complete = !columns.is_empty();
+        (columns, complete)
+    };
+
+    Some(WrittenSide {
+        table,
+        columns,
+        complete,
+    })
+}
+
+/// Columns listed so far on a parenthesised tuple, as in `items.(id, `.
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+struct ColumnTuple {
+    listed: Vec<String>,
+    /// Table and columns on the other side of the relationship, once complete
+    opposite: Option<(String, Vec<String>)>,
+}
+
 #[allow(non_camel_case_types)]
 #[derive(Eq, PartialEq, Clone)]
 enum CursorLocation {
@@ -270,5 +806,100 @@ enum CursorLocation {
     Enum,
     /// We are inside a relationship
     TableField_Table,
-    TableField_Field(String),
+    /// After the table of a relationship, either as `users.` or inside a tuple as `users.(`
+    TableField_Field(String, ColumnTuple),
+    /// After a comma on the attribute list, holding the settings already written
+    FieldAttributeList_Remaining(Vec<String>),
+    /// After `default:` on the attribute list, holding the type of the column
+    FieldAttributeList_Default(String),
+    /// Right after `ref:` on the attribute list
+    InlineRef_Operator,
+    /// After the operator of an inline relationship
+    InlineRef_Table,
+    /// After the table of an inline relationship, as in `ref: > users.`
+    InlineRef_Field(String),
+    /// Inside a composite index of the table, holding the columns already listed
+    Index_Columns(String, Vec<String>),
+    /// On an index of the table, either as its column or inside a backtick expression
+    Index_Column(String),
+    /// On the settings of an index, holding the settings already written
+    IndexSettings_Remaining(Vec<String>),
+    /// After `type:` on the settings of an index
+    IndexSettings_Type,
+    /// On the settings of a standalone relationship, holding the settings already written
+    RelationshipSettings_Remaining(Vec<String>),
+    /// After `delete:` or `update:` on the settings of a standalone relationship
+    RelationshipSettings_Action,
+    /// Outside of any block, where new blocks are written
+    TopLevel,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Table, columns and completeness of a side, which is easier to compare.
+    type Side = Option<(String, Vec<String>, bool)>;
+
+    fn side(side: Option<WrittenSide>) -> Side {
+        side.map(|side| (side.table, side.columns, side.complete))
+    }
+
+    fn expected(table: &str, columns: &[&str], complete: bool) -> Side {
+        Some((
+            table.to_string(),
+            columns.iter().map(|column| column.to_string()).collect(),
+            complete,
+        ))
+    }
+
+    fn sides(line: &str) -> Option<(Side, usize, Side)> {
+        written_relationship(line)
+            .map(|(left, operator, right)| (side(left), operator, side(right)))
+    }
+
+    #[test]
+    fn written_relationship_splits_on_the_operator() {
+        assert_eq!(
+            sides("Ref: orders.user_id > users.id"),
+            Some((
+                expected("orders", &["user_id"], true),
+                20,
+                expected("users", &["id"], true)
+            ))
+        );
+        assert_eq!(
+            sides("Ref fk: a.b <> c.d"),
+            Some((expected("a", &["b"], true), 12, expected("c", &["d"], true)))
+        );
+        assert_eq!(sides("Ref: a.b"), None);
+    }
+
+    #[test]
+    fn written_relationship_reads_composite_sides() {
+        assert_eq!(
+            sides("Ref: orders.(id, user_id) > users.(id, "),
+            Some((
+                expected("orders", &["id", "user_id"], true),
+                26,
+                expected("users", &["id"], false)
+            ))
+        );
+    }
+
+    #[test]
+    fn written_relationship_ignores_comments_quotes_and_settings() {
+        assert_eq!(
+            sides("Ref: a.b - c.d [delete: cascade] // e.f < g.h"),
+            Some((expected("a", &["b"], true), 9, expected("c", &["d"], true)))
+        );
+        assert_eq!(
+            sides("Ref: \"my-table\".id > b.id /* [, */"),
+            Some((
+                expected("my-table", &["id"], true),
+                19,
+                expected("b", &["id"], true)
+            ))
+        );
+    }
 }
@@ -1,58 +1,103 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
+#[cfg(unix)]
+use std::{os::unix::fs::FileTypeExt, path::PathBuf};
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use log::info;
-use tokio::sync::Mutex;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::Mutex,
+};
 use tower_lsp::{
     lsp_types::{
-        CompletionItem, CompletionOptions, CompletionParams, CompletionResponse,
-        DidChangeTextDocumentParams, DidOpenTextDocumentParams, DidSaveTextDocumentParams,
-        InitializeParams, InitializeResult, InitializedParams, MessageType, RenameParams,
-        RenameProviderCapability, ServerCapabilities, TextDocumentSyncCapability,
-        TextDocumentSyncKind, WorkspaceEdit,
+        CodeActionKind, CodeActionOptions, CodeActionParams, CodeActionProviderCapability,
+        CodeActionResponse, CompletionOptions, CompletionParams, CompletionResponse,
+        DidChangeTextDocumentParams, DidChangeWatchedFilesParams,
+        DidChangeWatchedFilesRegistrationOptions, DidCloseTextDocumentParams,
+        DidOpenTextDocumentParams, DidSaveTextDocumentParams, DocumentFormattingParams,
+        DocumentRangeFormattingParams, DocumentSymbolParams, DocumentSymbolResponse,
+        FileChangeType, FileSystemWatcher, FoldingRange, FoldingRangeParams,
+        FoldingRangeProviderCapability, GotoDefinitionParams, GotoDefinitionResponse, Hover,
+        HoverParams, HoverProviderCapability, InitializeParams, InitializeResult,
+        InitializedParams, Location, MessageType, OneOf, PrepareRenameResponse, ReferenceParams,
+        Registration, RenameOptions, RenameParams, SemanticTokens, SemanticTokensFullOptions,
+        SemanticTokensOptions, SemanticTokensParams, SemanticTokensRangeParams,
+        SemanticTokensRangeResult, SemanticTokensResult, SemanticTokensServerCapabilities,
+        ServerCapabilities, SymbolInformation, TextDocumentPositionParams,
+        TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit, WorkDoneProgressOptions,
+        WorkspaceEdit, WorkspaceSymbolParams,
     },
     Client, LanguageServer, LspService, Server,
 };
 use tree_sitter::Tree;
+use url::Url;
 
 use dbml_language_server::{
-    file::{parse_file, read_file},
-    populate_identifiers, IdentifiersMap,
+    document::Document, file::find_dbml_files, providers::TypeCompatibility,
 };
 
 #[derive(Debug)]
 struct Backend {
     client: Client,
-    raw_source_code: Arc<Mutex<String>>,
-    parsed_source_code: Arc<Mutex<Option<Tree>>>,
-    identifier_list: Arc<Mutex<IdentifiersMap>>,
+    /// Open documents, released as soon as they are closed
+    documents: Arc<Mutex<HashMap<Url, Document>>>,
+    /// Folders searched for schemas which are not open
+    workspace_folders: Arc<Mutex<Vec<Url>>>,
+    /// Symbols of every schema on disk, indexed once and refreshed as files are saved or changed
+    disk_symbols: Arc<Mutex<HashMap<Url, Vec<SymbolInformation>>>>,
+    type_compatibility: Arc<Mutex<TypeCompatibility>>,
+    /// Whether the client expands tab stops on completions
+    snippet_support: Arc<Mutex<bool>>,
 }
 
 impl Backend {
-    async fn update_source_code_and_parse(&self, text_to_update: String) -> Result<()> {
-        let mut inner_last_text = self.raw_source_code.lock().await;
-        *inner_last_text = text_to_update.clone();
-
-        let mut inner_parsed_code = self.parsed_source_code.lock().await;
-        let tree = parse_file(text_to_update.as_bytes(), None);
-        *inner_parsed_code = tree;
+    /// Re-indexes the given files from disk, without blocking the other requests while parsing.
+    async fn index_files(&self, uris: Vec<Url>) {
+        if let Ok(indexed) = tokio::task::spawn_blocking(move || index_files(uris)).await {
+            self.disk_symbols.lock().await.extend(indexed);
+        }
+    }
 
-        Ok(())
+    /// Runs `f` over an open document and its tree, which are both needed by every provider.
+    ///
+    /// Other locks must be taken before calling this, so they are always taken in the same order.
+    async fn with_document<T>(
+        &self,
+        uri: &Url,
+        f: impl FnOnce(&Document, &Tree) -> T,
+    ) -> Option<T> {
+        let documents = self.documents.lock().await;
+        let document = documents.get(uri)?;
+        Some(f(document, document.tree()?))
     }
 
-    async fn populate_identifier_map(&self) -> Result<()> {
-        let source = self.raw_source_code.lock().await;
-        let parsed_code = self
-            .parsed_source_code
-            .lock()
-            .await
-            .as_ref()
-            .map(|tree| populate_identifiers(source.as_bytes(), tree.root_node()));
+    async fn publish_diagnostics(&self, uri: Url) {
+        let compatibility = self.type_compatibility.lock().await;
+        let documents = self.documents.lock().await;
+        let document = match documents.get(&uri) {
+            Some(document) => document,
+            None => return,
+        };
 
-        let mut afds = self.identifier_list.lock().await;
-        *afds = parsed_code.unwrap();
+        let diagnostics = document
+            .tree()
+            .map(|tree| {
+                let mut diagnostics =
+                    dbml_language_server::providers::syntax_diagnostics(document.source(), tree);
+                diagnostics.extend(dbml_language_server::providers::semantic_diagnostics(
+                    document.source(),
+                    tree,
+                    document.identifiers(),
+                    &compatibility,
+                    &uri,
+                ));
+                diagnostics
+            })
+            .unwrap_or_default();
 
-        Ok(())
+        self.client
+            .publish_diagnostics(uri.clone(), diagnostics, document.version())
+            .await;
     }
 }
 
@@ -60,22 +105,81 @@ impl Backend {
 impl LanguageServer for Backend {
     async fn initialize(
         &self,
-        _: InitializeParams,
+        params: InitializeParams,
     ) -> tower_lsp::jsonrpc::Result<InitializeResult> {
-        // Request full content each change
-        let text_sync_kind = TextDocumentSyncKind::Full;
-        // Trigger completion automatically on dot
-        let completion_characters = vec![".".to_string(), "[".to_string()];
+        let workspace_folders: Vec<Url> = match params.workspace_folders {
+            Some(folders) => folders.into_iter().map(|folder| folder.uri).collect(),
+            None => params.root_uri.into_iter().collect(),
+        };
+        *self.workspace_folders.lock().await = workspace_folders;
+
+        let extra_compatible_types = params
+            .initialization_options
+            .and_then(|options| options.get("typeCompatibility").cloned())
+            .and_then(|groups| serde_json::from_value::<TypeCompatibility>(groups).ok());
+        if let Some(extra_compatible_types) = extra_compatible_types {
+            let mut compatibility = self.type_compatibility.lock().await;
+            *compatibility = TypeCompatibility::default().with_groups(extra_compatible_types);
+        }
+
+        let snippet_support = params
+            .capabilities
+            .text_document
+            .and_then(|text_document| text_document.completion)
+            .and_then(|completion| completion.completion_item)
+            .and_then(|completion_item| completion_item.snippet_support)
+            .unwrap_or(false);
+        *self.snippet_support.lock().await = snippet_support;
+
+        // Request only the edited ranges on each change
+        let text_sync_kind = TextDocumentSyncKind::Incremental;
+        // Trigger completion automatically on dot, along attribute lists and inline refs, and on
+        // column tuples of composite relationships and indexes
+        let completion_characters = [".", "[", ",", ":", ">", "<", "("]
+            .iter()
+            .map(|character| character.to_string())
+            .collect::<Vec<_>>();
 
         let initialize = InitializeResult {
             capabilities: ServerCapabilities {
-                rename_provider: Some(RenameProviderCapability::Simple(true)),
+                rename_provider: Some(OneOf::Right(RenameOptions {
+                    prepare_provider: Some(true),
+                    work_done_progress_options: Default::default(),
+                })),
                 completion_provider: Some(CompletionOptions {
                     resolve_provider: None,
                     trigger_characters: Some(completion_characters),
+                    all_commit_characters: None,
                     work_done_progress_options: Default::default(),
                 }),
-                definition_provider: Some(false),
+                definition_provider: Some(OneOf::Left(true)),
+                references_provider: Some(OneOf::Left(true)),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                document_symbol_provider: Some(OneOf::Left(true)),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
+                document_formatting_provider: Some(OneOf::Left(true)),
+                document_range_formatting_provider: Some(OneOf::Left(true)),
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![
+                            CodeActionKind::QUICKFIX,
+                            CodeActionKind::REFACTOR_REWRITE,
+                        ]),
+                        work_done_progress_options: WorkDoneProgressOptions::default(),
+                        resolve_provider: None,
+                    },
+                )),
+                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+                semantic_tokens_provider: Some(
+                    SemanticTokensServerCapabilities::SemanticTokensOptions(
+                        SemanticTokensOptions {
+                            legend: dbml_language_server::providers::semantic_tokens_legend(),
+                            range: Some(true),
+                            full: Some(SemanticTokensFullOptions::Bool(true)),
+                            work_done_progress_options: Default::default(),
+                        },
+                    ),
+                ),
                 text_document_sync: Some(TextDocumentSyncCapability::Kind(text_sync_kind)),
                 ..Default::default()
             },
@@ -86,7 +190,42 @@ impl LanguageServer for Backend {
 
     async fn initialized(&self, _: InitializedParams) {
         self.client
-            .log_message(MessageType::Info, "server initialized!");
+            .log_message(MessageType::Info, "server initialized!")
+            .await;
+
+        // Keeps the index up to date with files changed outside of the editor
+        let watchers = DidChangeWatchedFilesRegistrationOptions {
+            watchers: vec![FileSystemWatcher {
+                glob_pattern: "**/*.dbml".to_string(),
+                kind: None,
+            }],
+        };
+        let registration = Registration {
+            id: "dbml-files".to_string(),
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: serde_json::to_value(watchers).ok(),
+        };
+        if self
+            .client
+            .register_capability(vec![registration])
+            .await
+            .is_err()
+        {
+            info!("client can't watch files, the index is only refreshed on save");
+        }
+
+        let workspace_folders = self.workspace_folders.lock().await.clone();
+        let files = tokio::task::spawn_blocking(move || {
+            workspace_folders
+                .iter()
+                .filter_map(|folder| folder.to_file_path().ok())
+                .flat_map(|folder| find_dbml_files(&folder))
+                .filter_map(|path| Url::from_file_path(path).ok())
+                .collect::<Vec<_>>()
+        })
+        .await
+        .unwrap_or_default();
+        self.index_files(files).await;
     }
 
     async fn shutdown(&self) -> tower_lsp::jsonrpc::Result<()> {
@@ -96,26 +235,64 @@ impl LanguageServer for Backend {
 
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
         let document = params.text_document;
-        self.update_source_code_and_parse(document.text).await.ok();
+        let uri = document.uri;
+
+        self.documents.lock().await.insert(
+            uri.clone(),
+            Document::new(document.text, Some(document.version)),
+        );
+        self.publish_diagnostics(uri).await;
 
         self.client
-            .log_message(MessageType::Log, "Opened file sucessfully.");
+            .log_message(MessageType::Log, "Opened file sucessfully.")
+            .await;
     }
 
-    async fn did_change(&self, mut params: DidChangeTextDocumentParams) {
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
         info!("did_change event");
 
         let document = params.text_document;
-        let changes = params.content_changes.remove(0).text;
-        self.update_source_code_and_parse(changes).await;
-        self.populate_identifier_map().await;
+        match self.documents.lock().await.get_mut(&document.uri) {
+            Some(open_document) => {
+                open_document.apply_changes(params.content_changes, Some(document.version))
+            }
+            // Changes only make sense on top of the text sent by didOpen
+            None => {
+                info!("ignoring changes to {}, which is not open", document.uri);
+                return;
+            }
+        }
+        self.publish_diagnostics(document.uri.clone()).await;
+
+        self.client
+            .log_message(MessageType::Log, document.uri)
+            .await;
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
+        let _ = self.documents.lock().await.remove(&uri);
 
-        self.client.log_message(MessageType::Log, document.uri);
+        // Diagnostics of closed documents should not linger on the editor
+        self.client.publish_diagnostics(uri, vec![], None).await;
     }
+
     async fn did_save(&self, params: DidSaveTextDocumentParams) {
-        let document = params.text_document;
         info!("save request");
-        self.client.log_message(MessageType::Log, "basingao");
+        self.index_files(vec![params.text_document.uri]).await;
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        let mut changed = vec![];
+        for change in params.changes {
+            match change.typ {
+                FileChangeType::Created | FileChangeType::Changed => changed.push(change.uri),
+                FileChangeType::Deleted => {
+                    let _ = self.disk_symbols.lock().await.remove(&change.uri);
+                }
+            }
+        }
+        self.index_files(changed).await;
     }
 
     async fn completion(
@@ -125,36 +302,152 @@ impl LanguageServer for Backend {
         info!("completion parameters: {:#?}", params);
 
         let current_pos = params.text_document_position.position;
+        let uri = params.text_document_position.text_document.uri;
         let context = params.context.unwrap();
 
-        let current_source_file = self.raw_source_code.lock().await.clone();
-        let current_tree = self.parsed_source_code.lock().await.clone();
-        let identifiers = self.identifier_list.lock().await;
-        info!("{:?}", identifiers);
+        let compatibility = self.type_compatibility.lock().await;
+        let snippet_support = *self.snippet_support.lock().await;
+
+        let completions_available = self
+            .with_document(&uri, |document, tree| {
+                info!("{:?}", document.identifiers());
+
+                dbml_language_server::providers::complete_at_point(
+                    document.source().to_string(),
+                    tree.clone(),
+                    document.identifiers(),
+                    current_pos,
+                    context,
+                    &compatibility,
+                    snippet_support,
+                )
+            })
+            .await
+            .flatten();
+
+        Ok(completions_available.map(CompletionResponse::from))
+    }
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<GotoDefinitionResponse>> {
+        let position = params.text_document_position_params.position;
+        let uri = params.text_document_position_params.text_document.uri;
+
+        Ok(self
+            .with_document(&uri, |document, tree| {
+                dbml_language_server::providers::goto_definition(
+                    document.source(),
+                    tree,
+                    document.identifiers(),
+                    position,
+                    uri.clone(),
+                )
+            })
+            .await
+            .flatten())
+    }
+
+    async fn hover(&self, params: HoverParams) -> tower_lsp::jsonrpc::Result<Option<Hover>> {
+        let position = params.text_document_position_params.position;
+        let uri = params.text_document_position_params.text_document.uri;
+
+        Ok(self
+            .with_document(&uri, |document, tree| {
+                dbml_language_server::providers::hover(
+                    document.source(),
+                    tree,
+                    document.identifiers(),
+                    position,
+                )
+            })
+            .await
+            .flatten())
+    }
+
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<DocumentSymbolResponse>> {
+        let uri = params.text_document.uri;
+
+        Ok(self
+            .with_document(&uri, |document, tree| {
+                let symbols =
+                    dbml_language_server::providers::document_symbols(document.source(), tree);
+                DocumentSymbolResponse::Nested(symbols)
+            })
+            .await)
+    }
 
-        if current_tree.is_none() {
-            return Ok(None);
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<SymbolInformation>>> {
+        let documents = self.documents.lock().await;
+        let mut symbols = vec![];
+        for (uri, document) in documents.iter() {
+            symbols.extend(dbml_language_server::providers::document_workspace_symbols(
+                document.identifiers(),
+                uri,
+            ));
         }
 
-        let completions_available = dbml_language_server::providers::complete_at_point(
-            current_source_file,
-            current_tree.unwrap(), // Safe to unwrap after the early return
-            &identifiers,
-            current_pos,
-            context,
-        );
+        for (uri, disk_symbols) in self.disk_symbols.lock().await.iter() {
+            // Open documents are more recent than their contents on disk
+            if !documents.contains_key(uri) {
+                symbols.extend(disk_symbols.iter().cloned());
+            }
+        }
 
-        // if completions are available, we map them into a proper response
-        let completion_response = completions_available.map(|all_completions| {
-            let to_completion_items = all_completions
-                .iter()
-                .map(|each| CompletionItem::new_simple(each.to_string(), each.to_string()))
-                .collect::<Vec<CompletionItem>>();
-            CompletionResponse::from(to_completion_items)
-        });
+        Ok(Some(
+            dbml_language_server::providers::rank_workspace_symbols(&params.query, symbols),
+        ))
+    }
+
+    async fn references(
+        &self,
+        params: ReferenceParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<Location>>> {
+        let position = params.text_document_position.position;
+        let uri = params.text_document_position.text_document.uri;
+        let include_declaration = params.context.include_declaration;
+
+        Ok(self
+            .with_document(&uri, |document, tree| {
+                dbml_language_server::providers::references(
+                    document.source(),
+                    tree,
+                    document.identifiers(),
+                    position,
+                    include_declaration,
+                    uri.clone(),
+                )
+            })
+            .await
+            .flatten())
+    }
 
-        Ok(completion_response)
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<PrepareRenameResponse>> {
+        let position = params.position;
+        let uri = params.text_document.uri;
+
+        self.with_document(&uri, |document, tree| {
+            dbml_language_server::providers::prepare_rename(
+                document.source(),
+                tree,
+                document.identifiers(),
+                position,
+            )
+        })
+        .await
+        .transpose()
+        .map_err(tower_lsp::jsonrpc::Error::invalid_params)
     }
+
     async fn rename(
         &self,
         params: RenameParams,
@@ -163,39 +456,255 @@ impl LanguageServer for Backend {
         let position = params.text_document_position.position;
         let uri = params.text_document_position.text_document.uri;
 
-        let current_source_file = self.raw_source_code.lock().await.clone();
-        info!("source: {:?}", current_source_file);
+        Ok(self
+            .with_document(&uri, |document, tree| {
+                dbml_language_server::providers::rename(
+                    document.source(),
+                    tree,
+                    document.identifiers(),
+                    position,
+                    new_name,
+                    uri.clone(),
+                )
+            })
+            .await
+            .flatten())
+    }
+
+    async fn formatting(
+        &self,
+        params: DocumentFormattingParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<TextEdit>>> {
+        let uri = params.text_document.uri;
+
+        self.with_document(&uri, |document, tree| {
+            dbml_language_server::providers::format_document(
+                document.source(),
+                tree,
+                &params.options,
+            )
+        })
+        .await
+        .transpose()
+        .map_err(tower_lsp::jsonrpc::Error::invalid_params)
+    }
 
-        Ok(dbml_language_server::providers::rename(
-            current_source_file,
-            position,
-            new_name,
-            uri,
-        ))
+    async fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<TextEdit>>> {
+        let uri = params.text_document.uri;
+
+        self.with_document(&uri, |document, tree| {
+            dbml_language_server::providers::format_range(
+                document.source(),
+                tree,
+                &params.options,
+                params.range,
+            )
+        })
+        .await
+        .transpose()
+        .map_err(tower_lsp::jsonrpc::Error::invalid_params)
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<SemanticTokensResult>> {
+        let uri = params.text_document.uri;
+
+        Ok(self
+            .with_document(&uri, |document, tree| {
+                SemanticTokensResult::Tokens(SemanticTokens {
+                    result_id: None,
+                    data: dbml_language_server::providers::semantic_tokens(
+                        document.source(),
+                        tree,
+                        document.identifiers(),
+                        None,
+                    ),
+                })
+            })
+            .await)
+    }
+
+    async fn semantic_tokens_range(
+        &self,
+        params: SemanticTokensRangeParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<SemanticTokensRangeResult>> {
+        let uri = params.text_document.uri;
+
+        Ok(self
+            .with_document(&uri, |document, tree| {
+                SemanticTokensRangeResult::Tokens(SemanticTokens {
+                    result_id: None,
+                    data: dbml_language_server::providers::semantic_tokens(
+                        document.source(),
+                        tree,
+                        document.identifiers(),
+                        Some(params.range),
+                    ),
+                })
+            })
+            .await)
+    }
+
+    async fn code_action(
+        &self,
+        params: CodeActionParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri;
+
+        Ok(self
+            .with_document(&uri, |document, tree| {
+                dbml_language_server::providers::code_actions(
+                    document.source(),
+                    tree,
+                    document.identifiers(),
+                    params.range,
+                    uri.clone(),
+                )
+            })
+            .await)
+    }
+
+    async fn folding_range(
+        &self,
+        params: FoldingRangeParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<FoldingRange>>> {
+        let uri = params.text_document.uri;
+
+        Ok(self
+            .with_document(&uri, |document, tree| {
+                dbml_language_server::providers::folding_ranges(document.source(), tree)
+            })
+            .await)
     }
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    env_logger::init();
+/// How the server talks to its client.
+#[derive(Debug)]
+enum Transport {
+    /// Spawned by the editor, talking over stdin and stdout
+    Stdio,
+    /// Listening on the given address, serving one client after another
+    Tcp(String),
+    /// Listening on the given socket path, serving one client after another
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
 
-    let mut listener = tokio::net::TcpListener::bind("127.0.0.1:9001").await?;
-    let (stream, _) = listener.accept().await?;
-    let (read, write) = tokio::io::split(stream);
+const USAGE: &str = "\
+Usage: dbml-language-server [OPTIONS]
+
+Options:
+    --stdio             Talk over stdin and stdout (default)
+    --port <PORT>       Listen for TCP connections on 127.0.0.1:<PORT>
+    --listen <ADDRESS>  Listen for TCP connections on <ADDRESS>, such as 0.0.0.0:9001
+    --socket <PATH>     Listen for connections on the Unix domain socket at <PATH>
+    --help              Print this message";
+
+impl Transport {
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut transport = Transport::Stdio;
+
+        while let Some(arg) = args.next() {
+            let mut value_of = |option: &str| {
+                args.next()
+                    .ok_or_else(|| anyhow!("missing value for `{}`\n\n{}", option, USAGE))
+            };
+
+            transport = match arg.as_str() {
+                "--stdio" => Transport::Stdio,
+                "--port" => Transport::Tcp(format!("127.0.0.1:{}", value_of("--port")?)),
+                "--listen" => Transport::Tcp(value_of("--listen")?),
+                #[cfg(unix)]
+                "--socket" => Transport::Unix(PathBuf::from(value_of("--socket")?)),
+                "--help" | "-h" => {
+                    println!("{}", USAGE);
+                    std::process::exit(0);
+                }
+                unknown => bail!("unknown option `{}`\n\n{}", unknown, USAGE),
+            };
+        }
+        Ok(transport)
+    }
+}
 
-    info!("Starting generic LSP Server..");
+/// Parses the files straight from disk, keeping their symbols by file.
+fn index_files(uris: Vec<Url>) -> Vec<(Url, Vec<SymbolInformation>)> {
+    uris.into_iter()
+        .map(|uri| {
+            let symbols = dbml_language_server::providers::file_workspace_symbols(&uri);
+            (uri, symbols)
+        })
+        .collect()
+}
 
+/// Serves a single client until it disconnects.
+async fn serve<I, O>(read: I, write: O)
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite + Unpin,
+{
     let (service, messages) = LspService::new(|client| Backend {
         client,
-        raw_source_code: Arc::new(Default::default()),
-        parsed_source_code: Arc::new(Default::default()),
-        identifier_list: Default::default(),
+        documents: Default::default(),
+        workspace_folders: Default::default(),
+        disk_symbols: Default::default(),
+        type_compatibility: Default::default(),
+        snippet_support: Default::default(),
     });
 
     Server::new(read, write)
         .interleave(messages)
         .serve(service)
         .await;
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+
+    let transport = Transport::from_args(std::env::args().skip(1))?;
+    info!("Starting DBML LSP Server over {:?}..", transport);
+
+    match transport {
+        Transport::Stdio => serve(tokio::io::stdin(), tokio::io::stdout()).await,
+        Transport::Tcp(address) => {
+            let listener = tokio::net::TcpListener::bind(address.as_str()).await?;
+            loop {
+                let (stream, peer) = listener.accept().await?;
+                info!("Client connected from {}", peer);
+
+                let (read, write) = tokio::io::split(stream);
+                serve(read, write).await;
+                info!("Client {} disconnected, waiting for the next one", peer);
+            }
+        }
+        #[cfg(unix)]
+        Transport::Unix(path) => {
+            // A socket left behind by a previous run would make binding fail, but anything else
+            // at that path is not ours to remove
+            match std::fs::symlink_metadata(&path) {
+                Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(&path)?,
+                Ok(_) => bail!("`{}` already exists and is not a socket", path.display()),
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+                Err(error) => return Err(error.into()),
+            }
+
+            let listener = tokio::net::UnixListener::bind(&path)?;
+            loop {
+                let (stream, _) = listener.accept().await?;
+                info!("Client connected on {}", path.display());
+
+                let (read, write) = tokio::io::split(stream);
+                serve(read, write).await;
+                info!("Client disconnected, waiting for the next one");
+            }
+        }
+    }
 
     Ok(())
 }
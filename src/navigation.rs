@@ -38,8 +38,8 @@ pub(crate) fn node_parent_identifier(
         let parent_kind = parent.kind();
 
         if parent_kind == kind {
-            println!("{:?}", parent.utf8_text(source));
-            println!(
+            debug!("{:?}", parent.utf8_text(source));
+            debug!(
                 "{:?}",
                 parent
                     .child_by_field_name("alias")
@@ -61,3 +61,122 @@ pub(crate) fn node_parent_identifier(
     }
     None
 }
+
+/// Retrieves the identifier under the cursor. Since the cursor usually sits right after the word
+/// being edited, the previous column is also considered.
+pub(crate) fn identifier_at_point(root_node: Node, point: Point) -> Option<Node> {
+    let previous_column = point.column.checked_sub(1).map(|column| {
+        let mut previous_point = point;
+        previous_point.column = column;
+        previous_point
+    });
+
+    std::iter::once(point)
+        .chain(previous_column)
+        .filter_map(|point| root_node.descendant_for_point_range(*point, *point))
+        .find(|node| node.kind() == "identifier")
+}
+
+/// Goes up recursively until a node of the given kind is found.
+pub(crate) fn parent_of_kind<'a>(node: Node<'a>, kind: &str) -> Option<Node<'a>> {
+    let mut current_parent = node.parent();
+
+    while let Some(parent) = current_parent {
+        if parent.kind() == kind {
+            return Some(parent);
+        } else if parent.kind() == "project_file" {
+            return None;
+        }
+        current_parent = parent.parent();
+    }
+    None
+}
+
+/// Collects every descendant of `node`, including itself, that matches the given kind.
+pub(crate) fn descendants_of_kind<'a>(node: Node<'a>, kind: &str) -> Vec<Node<'a>> {
+    let mut found = vec![];
+    if node.kind() == kind {
+        found.push(node);
+    }
+
+    for child in (0..node.child_count()).filter_map(|idx| node.child(idx)) {
+        found.extend(descendants_of_kind(child, kind));
+    }
+    found
+}
+
+/// Returns the column identifiers of a `table_field`. These are either a single column, or a
+/// parenthesised list of columns on composite relationships.
+pub(crate) fn table_field_columns(table_field: Node) -> Vec<Node> {
+    let table_start = table_field
+        .child_by_field_name("table")
+        .map(|table| table.start_byte());
+
+    (0..table_field.child_count())
+        .filter_map(|idx| table_field.child(idx))
+        .filter(|child| Some(child.start_byte()) != table_start)
+        .flat_map(|child| descendants_of_kind(child, "identifier"))
+        .collect()
+}
+
+/// Returns the type of a `field_declaration`, which immediately follows the field name.
+pub(crate) fn field_type(field_declaration: Node) -> Option<Node> {
+    field_declaration
+        .child_by_field_name("name")?
+        .next_sibling()
+}
+
+/// Returns the node naming each value of an `enum_definition`.
+pub(crate) fn enum_values(enum_definition: Node) -> Vec<Node> {
+    descendants_of_kind(enum_definition, "enum_value")
+        .into_iter()
+        .filter_map(|value| {
+            value
+                .child_by_field_name("name")
+                .or_else(|| value.named_child(0))
+        })
+        .collect()
+}
+
+/// Returns each setting of a `field_declaration`, such as `pk` or `default: 0`, as written.
+pub(crate) fn field_settings(source: &[u8], field_declaration: Node) -> Vec<String> {
+    descendants_of_kind(field_declaration, "field_attribute_list")
+        .first()
+        .map(|attributes| {
+            (0..attributes.named_child_count())
+                .filter_map(|idx| attributes.named_child(idx))
+                .filter_map(|attribute| attribute.utf8_text(source).ok())
+                .map(|attribute| attribute.trim().to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the text of the note set on the table body, as in `Note: 'Stores users'`.
+pub(crate) fn table_note(source: &[u8], table_definition: Node) -> Option<String> {
+    descendants_of_kind(table_definition, "note_definition")
+        .into_iter()
+        .find(|note| parent_of_kind(*note, "field_declaration").is_none())
+        .and_then(|note| note.utf8_text(source).ok())
+        .map(note_text)
+}
+
+/// Strips the `note:` or `Note { }` wrapping and the quotes around a note, returning its text.
+pub(crate) fn note_text(note: &str) -> String {
+    let mut text = note.trim();
+
+    if text.to_lowercase().starts_with("note") {
+        text = text[4..].trim_start();
+    }
+    text = text.trim_start_matches(':').trim();
+    if text.starts_with('{') && text.ends_with('}') {
+        text = text[1..text.len() - 1].trim();
+    }
+
+    for quote in &["'''", "'", "\""] {
+        if text.len() >= 2 * quote.len() && text.starts_with(quote) && text.ends_with(quote) {
+            return text[quote.len()..text.len() - quote.len()].to_string();
+        }
+    }
+    text.to_string()
+}
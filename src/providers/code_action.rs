@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, Range as LspRange, TextEdit, WorkspaceEdit,
+};
+use tree_sitter::{Node, Tree};
+use url::Url;
+
+use super::{
+    ref_conversion::ref_conversion_actions,
+    validation::{unresolved_references, Unresolved, UnresolvedReference},
+};
+use crate::{
+    navigation::descendants_of_kind,
+    relationships::relationships,
+    wrappers::{lsp_position, Point},
+    IdentifiersMap,
+};
+
+/// Type given to created columns when the other side of the relationship is unknown as well.
+const FALLBACK_COLUMN_TYPE: &str = "int";
+
+/// Quick fixes for the unresolved references within the range: creating the missing table,
+/// adding the missing column, or replacing a typo by its closest match. Relationships within the
+/// range can also be converted between their inline and standalone forms.
+pub fn code_actions(
+    source: &str,
+    tree: &Tree,
+    identifiers: &IdentifiersMap,
+    range: LspRange,
+    file_location: Url,
+) -> Vec<CodeActionOrCommand> {
+    let source = source.as_bytes();
+    let root_node = tree.root_node();
+
+    let mut actions = unresolved_references(source, root_node, identifiers)
+        .into_iter()
+        .filter(|reference| overlaps(reference.range.0, range))
+        .flat_map(|reference| {
+            let mut actions = reference
+                .suggestions
+                .iter()
+                .enumerate()
+                .map(|(idx, suggestion)| {
+                    let edit = TextEdit {
+                        range: reference.range.0,
+                        new_text: suggestion.to_string(),
+                    };
+                    // The closest match is the preferred fix
+                    quick_fix(
+                        format!("Change to `{}`", suggestion),
+                        &reference,
+                        edit,
+                        idx == 0,
+                        &file_location,
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            let creation = match &reference.kind {
+                Unresolved::Table => Some((
+                    format!("Create table `{}`", reference.name),
+                    create_table(source, root_node, identifiers, &reference),
+                )),
+                Unresolved::Field(table) => {
+                    let table_name = identifiers.table_name_of(table);
+                    add_column(source, root_node, identifiers, &reference, &table_name).map(
+                        |edit| {
+                            let title = format!(
+                                "Add column `{}` to table `{}`",
+                                reference.name, table_name
+                            );
+                            (title, edit)
+                        },
+                    )
+                }
+                Unresolved::Enum => None,
+            };
+            if let Some((title, edit)) = creation {
+                actions.push(quick_fix(title, &reference, edit, false, &file_location));
+            }
+            actions
+        })
+        .collect::<Vec<_>>();
+
+    actions.extend(ref_conversion_actions(
+        source,
+        root_node,
+        identifiers,
+        range,
+        &file_location,
+    ));
+    actions
+}
+
+fn quick_fix(
+    title: String,
+    reference: &UnresolvedReference,
+    edit: TextEdit,
+    is_preferred: bool,
+    file_location: &Url,
+) -> CodeActionOrCommand {
+    let mut changes = HashMap::new();
+    let _ = changes.insert(file_location.clone(), vec![edit]);
+
+    CodeActionOrCommand::CodeAction(CodeAction {
+        title,
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![reference.to_diagnostic()]),
+        edit: Some(WorkspaceEdit {
+            changes: Some(changes),
+            document_changes: None,
+            change_annotations: None,
+        }),
+        command: None,
+        is_preferred: Some(is_preferred),
+        disabled: None,
+        data: None,
+    })
+}
+
+/// Appends the table at the end of the document, holding every column referenced on it.
+fn create_table(
+    source: &[u8],
+    root_node: Node,
+    identifiers: &IdentifiersMap,
+    reference: &UnresolvedReference,
+) -> TextEdit {
+    let mut columns: Vec<(String, String)> = vec![];
+
+    for relationship in relationships(source, root_node) {
+        let sides = [
+            (&relationship.left, &relationship.right),
+            (&relationship.right, &relationship.left),
+        ];
+        for (side, other_side) in sides.iter() {
+            if side.table != reference.name {
+                continue;
+            }
+
+            let other_columns = other_side.column_names(source);
+            for (idx, column) in side.column_names(source).into_iter().enumerate() {
+                if columns.iter().any(|(name, _)| name == column) {
+                    continue;
+                }
+                let column_type = other_columns
+                    .get(idx)
+                    .and_then(|other_column| {
+                        let other_table = identifiers.table_name_of(&other_side.table);
+                        identifiers.field_of_table(&other_table, other_column)
+                    })
+                    .map_or(FALLBACK_COLUMN_TYPE.to_string(), |field| {
+                        field.r#type.clone()
+                    });
+                columns.push((column.to_string(), column_type));
+            }
+        }
+    }
+
+    let body = columns
+        .iter()
+        .map(|(name, column_type)| format!("  {} {}\n", name, column_type))
+        .collect::<String>();
+    let separator = if source.ends_with(b"\n") {
+        "\n"
+    } else {
+        "\n\n"
+    };
+
+    let position = lsp_position(source, root_node.end_byte());
+
+    TextEdit {
+        range: LspRange::new(position, position),
+        new_text: format!("{}Table {} {{\n{}}}\n", separator, reference.name, body),
+    }
+}
+
+/// Inserts the column right before the closing brace of the table, typed as the column on the
+/// other side of the relationship.
+fn add_column(
+    source: &[u8],
+    root_node: Node,
+    identifiers: &IdentifiersMap,
+    reference: &UnresolvedReference,
+    table_name: &str,
+) -> Option<TextEdit> {
+    let column_type = other_side_type(source, root_node, identifiers, reference)
+        .unwrap_or_else(|| FALLBACK_COLUMN_TYPE.to_string());
+    let column = format!("{} {}", reference.name, column_type);
+
+    let table = descendants_of_kind(root_node, "table_definition")
+        .into_iter()
+        .find(|table| {
+            table
+                .child_by_field_name("name")
+                .and_then(|name| name.utf8_text(source).ok())
+                == Some(table_name)
+        })?;
+    let closing_brace = table
+        .end_byte()
+        .checked_sub(1)
+        .filter(|idx| source[*idx] == b'}')?;
+    let table_indentation = indentation_before(source, table.start_byte()).unwrap_or_default();
+
+    let (start, end, new_text) = match indentation_before(source, closing_brace) {
+        // The brace has a line of its own, which the column goes right above
+        Some(brace_indentation) => {
+            let line_start = closing_brace - brace_indentation.len();
+            let indentation = descendants_of_kind(table, "field_declaration")
+                .last()
+                .and_then(|field| indentation_before(source, field.start_byte()))
+                .unwrap_or_else(|| format!("{}  ", table_indentation));
+            (
+                line_start,
+                line_start,
+                format!("{}{}\n", indentation, column),
+            )
+        }
+        // Blocks on a single line, as in `Table users {}`, are broken over several lines
+        None => {
+            let content_end = source[..closing_brace]
+                .iter()
+                .rposition(|byte| !byte.is_ascii_whitespace())
+                .map_or(0, |idx| idx + 1);
+            (
+                content_end,
+                closing_brace,
+                format!("\n{}  {}\n{}", table_indentation, column, table_indentation),
+            )
+        }
+    };
+
+    Some(TextEdit {
+        range: LspRange::new(lsp_position(source, start), lsp_position(source, end)),
+        new_text,
+    })
+}
+
+/// Whitespace between the start of the line and the byte, or `None` if there is anything else.
+fn indentation_before(source: &[u8], byte: usize) -> Option<String> {
+    let line_start = source[..byte]
+        .iter()
+        .rposition(|byte| *byte == b'\n')
+        .map_or(0, |newline| newline + 1);
+    let before = &source[line_start..byte];
+
+    if before.iter().all(u8::is_ascii_whitespace) {
+        Some(String::from_utf8_lossy(before).to_string())
+    } else {
+        None
+    }
+}
+
+/// Type of the column matching the unresolved one on the other side of its relationship.
+fn other_side_type(
+    source: &[u8],
+    root_node: Node,
+    identifiers: &IdentifiersMap,
+    reference: &UnresolvedReference,
+) -> Option<String> {
+    let reference_start = Point::from_lsp(source, reference.range.0.start);
+
+    relationships(source, root_node)
+        .iter()
+        .find_map(|relationship| {
+            let sides = [
+                (&relationship.left, &relationship.right),
+                (&relationship.right, &relationship.left),
+            ];
+            sides.iter().find_map(|(side, other_side)| {
+                let idx = side
+                    .columns
+                    .iter()
+                    .position(|column| column.start_position() == *reference_start)?;
+                let other_column = *other_side.column_names(source).get(idx)?;
+                let other_table = identifiers.table_name_of(&other_side.table);
+                identifiers
+                    .field_of_table(&other_table, other_column)
+                    .map(|field| field.r#type.clone())
+            })
+        })
+}
+
+pub(crate) fn overlaps(a: LspRange, b: LspRange) -> bool {
+    a.start <= b.end && b.start <= a.end
+}
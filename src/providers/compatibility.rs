@@ -0,0 +1,164 @@
+use serde::Deserialize;
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, Location,
+};
+use tree_sitter::Tree;
+use url::Url;
+
+use crate::{relationships::relationships, wrappers::Range, IdentifiersMap};
+
+/// Types which are spelled differently, but stand for the same column type.
+const DEFAULT_COMPATIBLE_TYPES: &[&[&str]] = &[
+    &["int", "integer", "int4", "serial", "serial4"],
+    &["bigint", "int8", "bigserial", "serial8"],
+    &["smallint", "int2", "smallserial", "serial2"],
+    &["varchar", "character varying", "nvarchar", "string", "text"],
+    &["char", "character", "nchar"],
+    &["bool", "boolean"],
+    &["float", "float8", "double", "double precision"],
+    &["real", "float4"],
+    &["decimal", "numeric"],
+    &["timestamp", "datetime", "timestamp without time zone"],
+    &["timestamptz", "timestamp with time zone"],
+];
+
+/// Groups of types that can be related to each other.
+///
+/// Clients can extend the default groups with the `typeCompatibility` initialization option,
+/// such as `{ "typeCompatibility": [["uuid", "char(36)"]] }`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(transparent)]
+pub struct TypeCompatibility {
+    groups: Vec<Vec<String>>,
+}
+
+impl Default for TypeCompatibility {
+    fn default() -> Self {
+        let groups = DEFAULT_COMPATIBLE_TYPES
+            .iter()
+            .map(|group| group.iter().map(|c| c.to_string()).collect())
+            .collect();
+        TypeCompatibility { groups }
+    }
+}
+
+impl TypeCompatibility {
+    /// Adds groups on top of the default ones.
+    pub fn with_groups(mut self, extra: TypeCompatibility) -> Self {
+        self.groups.extend(extra.groups);
+        self
+    }
+
+    pub(crate) fn is_compatible(&self, a: &str, b: &str) -> bool {
+        let (a, b) = (normalize_type(a), normalize_type(b));
+        if a == b {
+            return true;
+        }
+
+        self.groups.iter().any(|group| {
+            let contains = |type_name: &str| {
+                group
+                    .iter()
+                    .any(|member| normalize_type(member) == type_name)
+            };
+            contains(&a) && contains(&b)
+        })
+    }
+}
+
+/// Lowercases the type and strips its arguments, so `VARCHAR(255)` is the same as `varchar`.
+fn normalize_type(type_name: &str) -> String {
+    type_name
+        .trim_matches('"')
+        .split('(')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase()
+}
+
+/// Compares the columns on both sides of every relationship, reporting those with incompatible
+/// types, or with a different number of columns on composite relationships.
+pub(crate) fn compatibility_diagnostics(
+    source: &[u8],
+    tree: &Tree,
+    identifiers: &IdentifiersMap,
+    compatibility: &TypeCompatibility,
+    file_location: &Url,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+
+    for relationship in relationships(source, tree.root_node()) {
+        let (left, right) = (&relationship.left, &relationship.right);
+
+        if left.columns.len() != right.columns.len() {
+            let range = right
+                .table_field
+                .map_or(relationship.operator.range(), |table_field| {
+                    table_field.range()
+                });
+
+            diagnostics.push(Diagnostic {
+                range: Range::from_tree(source, range).0,
+                severity: Some(DiagnosticSeverity::Error),
+                source: Some("dbml".to_string()),
+                message: format!(
+                    "relationship links {} column(s) of `{}` to {} column(s) of `{}`",
+                    left.columns.len(),
+                    left.table,
+                    right.columns.len(),
+                    right.table
+                ),
+                ..Default::default()
+            });
+            continue;
+        }
+
+        let column_pairs = left
+            .column_names(source)
+            .into_iter()
+            .zip(right.column_names(source))
+            .zip(right.columns.iter());
+
+        for ((left_column, right_column), right_node) in column_pairs {
+            // Unresolved columns are already reported by the validation pass
+            let left_field = identifiers.field_of_table(&left.table, left_column);
+            let right_field = identifiers.field_of_table(&right.table, right_column);
+            let (left_field, right_field) = match (left_field, right_field) {
+                (Some(left_field), Some(right_field)) => (left_field, right_field),
+                _ => continue,
+            };
+
+            if compatibility.is_compatible(&left_field.r#type, &right_field.r#type) {
+                continue;
+            }
+
+            diagnostics.push(Diagnostic {
+                range: Range::from_tree(source, right_node.range()).0,
+                severity: Some(DiagnosticSeverity::Warning),
+                source: Some("dbml".to_string()),
+                message: format!(
+                    "type `{}` of `{}.{}` is not compatible with type `{}` of `{}.{}`",
+                    left_field.r#type,
+                    left.table,
+                    left_column,
+                    right_field.r#type,
+                    right.table,
+                    right_column
+                ),
+                related_information: Some(vec![
+                    DiagnosticRelatedInformation {
+                        location: Location::new(file_location.clone(), left_field.range.0),
+                        message: format!("`{}.{}` is defined here", left.table, left_column),
+                    },
+                    DiagnosticRelatedInformation {
+                        location: Location::new(file_location.clone(), right_field.range.0),
+                        message: format!("`{}.{}` is defined here", right.table, right_column),
+                    },
+                ]),
+                ..Default::default()
+            });
+        }
+    }
+    diagnostics
+}
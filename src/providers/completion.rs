@@ -1,37 +1,169 @@
-use tower_lsp::lsp_types::{CompletionContext, Position as LspPosition};
+use tower_lsp::lsp_types::{
+    CompletionContext, CompletionItem, CompletionItemKind, Documentation, InsertTextFormat,
+    MarkupContent, MarkupKind, Position as LspPosition, Range as LspRange, TextEdit,
+};
 use tree_sitter::Tree;
 
-use crate::{find_location_on_ast, CursorLocation, IdentifiersMap};
+use super::compatibility::TypeCompatibility;
+use crate::{
+    find_location_on_ast, line_prefix, relationships::CARDINALITY_OPERATORS, ColumnTuple,
+    CursorLocation, FieldInfo, IdentifiersMap,
+};
 
-const FIELD_ATTRIBUTES: &[&str] = &["not null", "null", "pk", "unique", "increment", "ref:"];
-const KEYWORDS: &[&str] = &["table", "enum", "ref:"];
-const PRIMITIVE_TYPES: &[&str] = &["int", "float", "text", "varchar"];
+const FIELD_ATTRIBUTES: &[&str] = &[
+    "not null",
+    "null",
+    "pk",
+    "unique",
+    "increment",
+    "default:",
+    "ref:",
+];
+const INDEX_SETTINGS: &[&str] = &["pk", "unique", "name:", "type:", "note:"];
+const INDEX_TYPES: &[&str] = &["btree", "hash"];
+pub(crate) const RELATIONSHIP_SETTINGS: &[&str] = &["delete:", "update:", "color:"];
+pub(crate) const REFERENTIAL_ACTIONS: &[&str] = &[
+    "cascade",
+    "restrict",
+    "set null",
+    "set default",
+    "no action",
+];
+pub(crate) const KEYWORDS: &[&str] = &["table", "enum", "ref:"];
+pub(crate) const PRIMITIVE_TYPES: &[&str] = &["int", "float", "text", "varchar"];
 
+/// Label, description and skeleton, with its tab stops, of each top level block.
+const BLOCK_SNIPPETS: &[(&str, &str, &str)] = &[
+    (
+        "Table",
+        "table definition",
+        "Table ${1:name} {\n  id int [pk]\n  $0\n}",
+    ),
+    (
+        "Enum",
+        "enum definition",
+        "Enum ${1:name} {\n  ${2:value}\n  $0\n}",
+    ),
+    (
+        "Ref",
+        "standalone relationship",
+        "Ref: ${1:table}.${2:column} ${3|>,<,-,<>|} ${4:table}.${5:column}$0",
+    ),
+    (
+        "Project",
+        "project definition",
+        "Project ${1:name} {\n  database_type: '${2|PostgreSQL,MySQL,SQL Server,Oracle,SQLite|}'\n  \
+         $0\n}",
+    ),
+    (
+        "TableGroup",
+        "group of tables",
+        "TableGroup ${1:name} {\n  ${2:table}\n  $0\n}",
+    ),
+];
+
+/// Completes the symbol under the cursor. Items are ranked in the order they are suggested, and
+/// replace the part of the word already typed.
+///
+/// Clients without snippet support get the skeletons of blocks without their tab stops.
 pub fn complete_at_point(
     source: String,
     tree: Tree,
     identifiers: &IdentifiersMap,
     edit_position: LspPosition,
     context: CompletionContext,
-) -> Option<Vec<String>> {
+    compatibility: &TypeCompatibility,
+    snippet_support: bool,
+) -> Option<Vec<CompletionItem>> {
     let root_node = tree.root_node();
     let completion_character = context.trigger_character.as_deref();
 
     let valid_position = find_location_on_ast(source.as_bytes(), root_node, edit_position);
+    let items = location_items(
+        identifiers,
+        compatibility,
+        &valid_position,
+        completion_character,
+        snippet_support,
+    )?;
 
-    if CursorLocation::TableField_Table == valid_position {
-        let tables_identifiers = identifiers.tables()?;
-        return Some(tables_identifiers);
-    }
+    let replaced_range = replaced_range(
+        &line_prefix(source.as_bytes(), edit_position),
+        edit_position,
+        is_setting(&valid_position) || completion_character == Some("["),
+    );
+    Some(
+        items
+            .into_iter()
+            .enumerate()
+            .map(|(idx, mut item)| {
+                let new_text = item
+                    .insert_text
+                    .take()
+                    .unwrap_or_else(|| item.label.clone());
+                item.sort_text = Some(format!("{:04}", idx));
+                item.text_edit = Some(TextEdit::new(replaced_range, new_text).into());
+                item
+            })
+            .collect(),
+    )
+}
 
-    if let CursorLocation::TableField_Field(table_name) = valid_position {
-        let fields = identifiers.fields_of_table(&table_name)?;
-        return Some(
-            fields
-                .iter()
-                .map(|field| field.text_name.to_owned())
-                .collect(),
-        );
+fn location_items(
+    identifiers: &IdentifiersMap,
+    compatibility: &TypeCompatibility,
+    valid_position: &CursorLocation,
+    completion_character: Option<&str>,
+    snippet_support: bool,
+) -> Option<Vec<CompletionItem>> {
+    match valid_position {
+        CursorLocation::TopLevel => return Some(snippet_items(snippet_support)),
+        CursorLocation::InlineRef_Operator => return Some(operator_items()),
+        CursorLocation::FieldAttributeList_Default(field_type) => {
+            return enum_value_items(identifiers, field_type);
+        }
+        CursorLocation::FieldAttributeList_Remaining(written) => {
+            return Some(keyword_items(&remaining_settings(
+                FIELD_ATTRIBUTES,
+                written,
+            )));
+        }
+        CursorLocation::IndexSettings_Remaining(written) => {
+            return Some(keyword_items(&remaining_settings(INDEX_SETTINGS, written)));
+        }
+        CursorLocation::RelationshipSettings_Remaining(written) => {
+            return Some(keyword_items(&remaining_settings(
+                RELATIONSHIP_SETTINGS,
+                written,
+            )));
+        }
+        CursorLocation::RelationshipSettings_Action => {
+            return Some(keyword_items(REFERENTIAL_ACTIONS));
+        }
+        CursorLocation::IndexSettings_Type => return Some(keyword_items(INDEX_TYPES)),
+        CursorLocation::Index_Column(table_name) | CursorLocation::InlineRef_Field(table_name) => {
+            return Some(
+                identifiers
+                    .fields_of_table(table_name)?
+                    .iter()
+                    .map(column_item)
+                    .collect(),
+            );
+        }
+        CursorLocation::TableField_Field(table_name, tuple) => {
+            return tuple_columns(identifiers, compatibility, table_name, tuple);
+        }
+        CursorLocation::Index_Columns(table_name, listed) => {
+            let tuple = ColumnTuple {
+                listed: listed.clone(),
+                opposite: None,
+            };
+            return tuple_columns(identifiers, compatibility, table_name, &tuple);
+        }
+        CursorLocation::TableField_Table | CursorLocation::InlineRef_Table => {
+            return table_items(identifiers);
+        }
+        _ => {}
     }
 
     // dentro de tabelas
@@ -39,26 +171,330 @@ pub fn complete_at_point(
     // caso estiver dentro de um field_declaration_list
     // é por conta que está dentro de uma tabela
     // fornecer atributos e enums se for o caso
-    if CursorLocation::Field == valid_position {
-        return Some(
-            [
-                identifiers.enums_without_discriminants.as_slice(),
-                PRIMITIVE_TYPES
-                    .iter()
-                    .map(|c| c.to_string())
-                    .collect::<Vec<_>>()
-                    .as_slice(),
-            ]
-            .concat(),
-        );
+    if CursorLocation::Field == *valid_position {
+        return Some(type_items(identifiers));
     }
 
     // se o current node for field_attribute_list
     // ou receber o caractere '['
     // fornecer lista de atributos
     // TODO: concat with available enums
-    if completion_character == Some("[") || valid_position == CursorLocation::FieldAttributeList {
-        return Some(FIELD_ATTRIBUTES.iter().map(|c| c.to_string()).collect());
+    if completion_character == Some("[") || *valid_position == CursorLocation::FieldAttributeList {
+        return Some(keyword_items(FIELD_ATTRIBUTES));
     }
     None
 }
+
+/// Whether a whole setting is being written, such as `not null` or `set default`, which can hold
+/// spaces, instead of a single identifier.
+fn is_setting(valid_position: &CursorLocation) -> bool {
+    matches!(
+        valid_position,
+        CursorLocation::FieldAttributeList
+            | CursorLocation::FieldAttributeList_Default(_)
+            | CursorLocation::FieldAttributeList_Remaining(_)
+            | CursorLocation::InlineRef_Operator
+            | CursorLocation::IndexSettings_Remaining(_)
+            | CursorLocation::IndexSettings_Type
+            | CursorLocation::RelationshipSettings_Remaining(_)
+            | CursorLocation::RelationshipSettings_Action
+    )
+}
+
+/// Range of what was already typed of the completed word or setting.
+fn replaced_range(line_prefix: &str, edit_position: LspPosition, whole_setting: bool) -> LspRange {
+    let typed_start = if whole_setting {
+        let setting_start = line_prefix
+            .rfind(|c| c == '[' || c == ',' || c == ':')
+            .map_or(0, |idx| idx + 1);
+        let setting = &line_prefix[setting_start..];
+        setting_start + setting.len() - setting.trim_start().len()
+    } else {
+        line_prefix
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !(c.is_alphanumeric() || *c == '_'))
+            .map_or(0, |(idx, c)| idx + c.len_utf8())
+    };
+
+    // Positions count UTF-16 code units
+    let typed_len = line_prefix[typed_start..].encode_utf16().count() as u32;
+    LspRange::new(
+        LspPosition::new(
+            edit_position.line,
+            edit_position.character.saturating_sub(typed_len),
+        ),
+        edit_position,
+    )
+}
+
+fn keyword_items(keywords: &[&str]) -> Vec<CompletionItem> {
+    keywords
+        .iter()
+        .map(|keyword| CompletionItem {
+            label: keyword.to_string(),
+            kind: Some(CompletionItemKind::Keyword),
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn snippet_items(snippet_support: bool) -> Vec<CompletionItem> {
+    BLOCK_SNIPPETS
+        .iter()
+        .map(|(label, detail, snippet)| {
+            let (insert_text, insert_text_format) = if snippet_support {
+                (snippet.to_string(), InsertTextFormat::Snippet)
+            } else {
+                (without_tab_stops(snippet), InsertTextFormat::PlainText)
+            };
+            CompletionItem {
+                label: label.to_string(),
+                kind: Some(CompletionItemKind::Snippet),
+                detail: Some(detail.to_string()),
+                insert_text: Some(insert_text),
+                insert_text_format: Some(insert_text_format),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// Plain text of a snippet, with each tab stop replaced by its placeholder, or by its first
+/// choice, as in `${1:name}` and `${3|>,<|}`.
+fn without_tab_stops(snippet: &str) -> String {
+    let mut text = String::with_capacity(snippet.len());
+    let mut rest = snippet;
+
+    while let Some(tab_stop) = rest.find('$') {
+        text.push_str(&rest[..tab_stop]);
+        rest = &rest[tab_stop + 1..];
+
+        if rest.starts_with('{') {
+            let tab_stop_end = rest.find('}').unwrap_or(rest.len() - 1);
+            let body = rest[1..tab_stop_end].trim_start_matches(|c: char| c.is_ascii_digit());
+            if body.starts_with('|') {
+                text.push_str(
+                    body[1..]
+                        .split(|c| c == ',' || c == '|')
+                        .next()
+                        .unwrap_or_default(),
+                );
+            } else if body.starts_with(':') {
+                text.push_str(&body[1..]);
+            }
+            rest = &rest[tab_stop_end + 1..];
+        } else {
+            rest = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+fn operator_items() -> Vec<CompletionItem> {
+    CARDINALITY_OPERATORS
+        .iter()
+        .map(|operator| {
+            let cardinality = match *operator {
+                ">" => "many-to-one",
+                "<" => "one-to-many",
+                "-" => "one-to-one",
+                _ => "many-to-many",
+            };
+            CompletionItem {
+                label: operator.to_string(),
+                kind: Some(CompletionItemKind::Operator),
+                detail: Some(cardinality.to_string()),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// Tables and their aliases, documented by the note of the table.
+fn table_items(identifiers: &IdentifiersMap) -> Option<Vec<CompletionItem>> {
+    let mut tables = identifiers.tables()?;
+    tables.sort();
+
+    Some(
+        tables
+            .into_iter()
+            .map(|table_or_alias| {
+                let table_name = identifiers.table_name_of(&table_or_alias);
+                let detail = if table_name != table_or_alias {
+                    Some(format!("alias of {}", table_name))
+                } else {
+                    None
+                };
+                let documentation = identifiers
+                    .table_info(&table_name)
+                    .and_then(|table| table.note.as_deref())
+                    .map(markdown);
+
+                CompletionItem {
+                    label: table_or_alias,
+                    kind: Some(CompletionItemKind::Class),
+                    detail,
+                    documentation,
+                    ..Default::default()
+                }
+            })
+            .collect(),
+    )
+}
+
+fn column_item(field: &FieldInfo) -> CompletionItem {
+    CompletionItem {
+        label: field.text_name.clone(),
+        kind: Some(CompletionItemKind::Field),
+        detail: Some(field.r#type.clone()),
+        documentation: field.note.as_deref().map(markdown),
+        ..Default::default()
+    }
+}
+
+/// Enums defined on the document, followed by primitive types.
+fn type_items(identifiers: &IdentifiersMap) -> Vec<CompletionItem> {
+    let enums = identifiers
+        .enums_without_discriminants
+        .iter()
+        .map(|enum_name| {
+            let values = identifiers.enum_info(enum_name).map(|enum_info| {
+                let values = enum_info
+                    .values
+                    .iter()
+                    .map(|value| format!("`{}`", value))
+                    .collect::<Vec<_>>();
+                markdown(&values.join(" | "))
+            });
+            CompletionItem {
+                label: enum_name.to_string(),
+                kind: Some(CompletionItemKind::Enum),
+                detail: Some("enum".to_string()),
+                documentation: values,
+                ..Default::default()
+            }
+        });
+    let primitive_types = PRIMITIVE_TYPES.iter().map(|type_name| CompletionItem {
+        label: type_name.to_string(),
+        kind: Some(CompletionItemKind::TypeParameter),
+        ..Default::default()
+    });
+
+    enums.chain(primitive_types).collect()
+}
+
+/// Values of the enum, quoted as defaults are written. Columns of other types get none.
+fn enum_value_items(identifiers: &IdentifiersMap, enum_name: &str) -> Option<Vec<CompletionItem>> {
+    let enum_info = identifiers.enum_info(enum_name)?;
+
+    Some(
+        enum_info
+            .values
+            .iter()
+            .map(|value| value.trim_matches('"'))
+            .map(|value| CompletionItem {
+                label: value.to_string(),
+                kind: Some(CompletionItemKind::EnumMember),
+                detail: Some(enum_name.to_string()),
+                insert_text: Some(format!("'{}'", value)),
+                ..Default::default()
+            })
+            .collect(),
+    )
+}
+
+fn markdown(value: &str) -> Documentation {
+    Documentation::MarkupContent(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value: value.to_string(),
+    })
+}
+
+/// Columns of the table not listed yet on the tuple. Once the other side of the relationship is
+/// complete, a tuple of columns with compatible types is suggested first.
+fn tuple_columns(
+    identifiers: &IdentifiersMap,
+    compatibility: &TypeCompatibility,
+    table_name: &str,
+    tuple: &ColumnTuple,
+) -> Option<Vec<CompletionItem>> {
+    let fields = identifiers.fields_of_table(table_name)?;
+    let mut completions = vec![];
+
+    if let (Some((opposite_table, opposite_columns)), true) =
+        (&tuple.opposite, tuple.listed.is_empty())
+    {
+        let opposite_table = identifiers.table_name_of(opposite_table);
+        let mut matching: Vec<&FieldInfo> = vec![];
+
+        for column in opposite_columns {
+            let opposite_type = match identifiers.field_of_table(&opposite_table, column) {
+                Some(field) => &field.r#type,
+                None => break,
+            };
+            let compatible = fields.iter().find(|field| {
+                !matching
+                    .iter()
+                    .any(|matched| matched.text_name == field.text_name)
+                    && compatibility.is_compatible(&field.r#type, opposite_type)
+            });
+            match compatible {
+                Some(field) => matching.push(field),
+                None => break,
+            }
+        }
+
+        if matching.len() == opposite_columns.len() && matching.len() > 1 {
+            let names = matching
+                .iter()
+                .map(|field| field.text_name.as_str())
+                .collect::<Vec<_>>();
+            let types = matching
+                .iter()
+                .map(|field| field.r#type.as_str())
+                .collect::<Vec<_>>();
+            completions.push(CompletionItem {
+                label: names.join(", "),
+                kind: Some(CompletionItemKind::Field),
+                detail: Some(format!("({})", types.join(", "))),
+                ..Default::default()
+            });
+        }
+    }
+
+    completions.extend(
+        fields
+            .iter()
+            .filter(|field| !tuple.listed.contains(&field.text_name))
+            .map(column_item),
+    );
+    Some(completions)
+}
+
+/// Settings not written yet on the list. `null` and `not null` exclude each other.
+fn remaining_settings(settings: &[&'static str], written: &[String]) -> Vec<&'static str> {
+    let written_keys = written
+        .iter()
+        .map(|setting| {
+            let key = setting.split(':').next().unwrap_or_default();
+            match key.trim().to_lowercase().as_str() {
+                "null" | "not null" => "null".to_string(),
+                key => key.to_string(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    settings
+        .iter()
+        .filter(|setting| {
+            let key = match setting.trim_end_matches(':') {
+                "not null" => "null",
+                key => key,
+            };
+            !written_keys.iter().any(|written_key| written_key == key)
+        })
+        .copied()
+        .collect()
+}
@@ -0,0 +1,147 @@
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, Location,
+};
+use tree_sitter::{Node, Tree};
+use url::Url;
+
+use crate::wrappers::Range;
+
+/// Blocks which may enclose a syntax error, and how they are described to the user.
+const BLOCK_DESCRIPTIONS: &[(&str, &str)] = &[
+    ("table_definition", "table"),
+    ("enum_definition", "enum"),
+    ("indexes_definition", "indexes"),
+    ("ref_definition", "ref"),
+    ("project_definition", "project"),
+    ("table_group_definition", "table group"),
+];
+
+/// Longest snippet of source shown on messages about unexpected text.
+const MAX_SNIPPET_LENGTH: usize = 30;
+
+/// Converts every ERROR and MISSING node of the tree into a diagnostic.
+pub fn syntax_diagnostics(source: &str, tree: &Tree) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    collect_syntax_errors(source.as_bytes(), tree.root_node(), &mut diagnostics);
+    diagnostics
+}
+
+fn collect_syntax_errors(source: &[u8], node: Node, out_diagnostics: &mut Vec<Diagnostic>) {
+    if node.is_missing() {
+        out_diagnostics.push(error_diagnostic(
+            Range::from_tree(source, node.range()),
+            missing_message(source, node),
+        ));
+        return;
+    }
+
+    // Errors contain the nodes that failed to parse, so we stop descending here
+    if node.is_error() {
+        out_diagnostics.push(error_diagnostic(
+            Range::from_tree(source, node.range()),
+            unexpected_message(source, node),
+        ));
+        return;
+    }
+
+    if !node.has_error() {
+        return;
+    }
+
+    for child in (0..node.child_count()).filter_map(|idx| node.child(idx)) {
+        collect_syntax_errors(source, child, out_diagnostics);
+    }
+}
+
+pub(crate) fn error_diagnostic(range: Range, message: String) -> Diagnostic {
+    Diagnostic {
+        range: range.0,
+        severity: Some(DiagnosticSeverity::Error),
+        source: Some("dbml".to_string()),
+        message,
+        ..Default::default()
+    }
+}
+
+/// An error on `node` which links back to `first`, the definition it conflicts with.
+pub(crate) fn related_diagnostic(
+    source: &[u8],
+    node: Node,
+    first: Node,
+    message: String,
+    file_location: &Url,
+) -> Diagnostic {
+    Diagnostic {
+        related_information: Some(vec![DiagnosticRelatedInformation {
+            location: Location::new(
+                file_location.clone(),
+                Range::from_tree(source, first.range()).0,
+            ),
+            message: "first defined here".to_string(),
+        }]),
+        ..error_diagnostic(Range::from_tree(source, node.range()), message)
+    }
+}
+
+fn missing_message(source: &[u8], node: Node) -> String {
+    let expected = if node.is_named() {
+        node.kind().replace('_', " ")
+    } else {
+        format!("`{}`", node.kind())
+    };
+
+    match enclosing_block(source, node) {
+        Some(block) if node.kind() == "}" => format!("expected {} to close {}", expected, block),
+        Some(block) => format!("expected {} in {}", expected, block),
+        None => format!("expected {}", expected),
+    }
+}
+
+fn unexpected_message(source: &[u8], node: Node) -> String {
+    let text = node
+        .utf8_text(source)
+        .unwrap_or_default()
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .trim();
+
+    if text.is_empty() {
+        return "syntax error".to_string();
+    }
+
+    let snippet = match text.char_indices().nth(MAX_SNIPPET_LENGTH) {
+        Some((idx, _)) => format!("{}..", &text[..idx]),
+        None => text.to_string(),
+    };
+
+    match enclosing_block(source, node) {
+        Some(block) => format!("unexpected `{}` in {}", snippet, block),
+        None => format!("unexpected `{}`", snippet),
+    }
+}
+
+/// Describes the closest block enclosing the node, such as "table `users`".
+fn enclosing_block(source: &[u8], node: Node) -> Option<String> {
+    let mut current_parent = node.parent();
+
+    while let Some(parent) = current_parent {
+        let description = BLOCK_DESCRIPTIONS
+            .iter()
+            .find(|(kind, _)| *kind == parent.kind())
+            .map(|(_, description)| *description);
+
+        if let Some(description) = description {
+            let name = parent
+                .child_by_field_name("name")
+                .and_then(|name| name.utf8_text(source).ok());
+
+            return Some(match name {
+                Some(name) => format!("{} `{}`", description, name),
+                None => description.to_string(),
+            });
+        }
+        current_parent = parent.parent();
+    }
+    None
+}
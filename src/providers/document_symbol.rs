@@ -0,0 +1,148 @@
+use tower_lsp::lsp_types::{DocumentSymbol, SymbolKind};
+use tree_sitter::{Node, Tree};
+
+use crate::{
+    navigation::{descendants_of_kind, enum_values, field_type},
+    relationships::relationships,
+    wrappers::Range,
+};
+
+/// Builds the outline of the document, with tables holding their columns and indexes, and enums
+/// holding their values.
+pub fn document_symbols(source: &str, tree: &Tree) -> Vec<DocumentSymbol> {
+    let source = source.as_bytes();
+    let root_node = tree.root_node();
+
+    (0..root_node.named_child_count())
+        .filter_map(|idx| root_node.named_child(idx))
+        .filter_map(|node| match node.kind() {
+            "project_definition" => Some(symbol(source, node, SymbolKind::Module, "Project")),
+            "table_definition" => Some(table_symbol(source, node)),
+            "enum_definition" => Some(enum_symbol(source, node)),
+            "ref_definition" => Some(ref_symbol(source, node)),
+            "table_group_definition" => {
+                Some(symbol(source, node, SymbolKind::Namespace, "TableGroup"))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Symbol named after the `name` field of the node, or the fallback name if it has none.
+#[allow(deprecated)]
+fn symbol(source: &[u8], node: Node, kind: SymbolKind, fallback_name: &str) -> DocumentSymbol {
+    let name_node = node.child_by_field_name("name");
+    let name = name_node
+        .and_then(|name| name.utf8_text(source).ok())
+        .unwrap_or(fallback_name);
+
+    DocumentSymbol {
+        name: name.to_string(),
+        detail: None,
+        kind,
+        tags: None,
+        deprecated: None,
+        range: Range::from_tree(source, node.range()).0,
+        selection_range: Range::from_tree(source, name_node.unwrap_or(node).range()).0,
+        children: None,
+    }
+}
+
+#[allow(deprecated)]
+fn table_symbol(source: &[u8], table: Node) -> DocumentSymbol {
+    let mut columns = descendants_of_kind(table, "field_declaration")
+        .into_iter()
+        .filter_map(|field| {
+            let name = field.child_by_field_name("name")?;
+            Some(DocumentSymbol {
+                name: name.utf8_text(source).ok()?.to_string(),
+                detail: field_type(field)
+                    .and_then(|field_type| field_type.utf8_text(source).ok())
+                    .map(|field_type| field_type.to_string()),
+                kind: SymbolKind::Field,
+                tags: None,
+                deprecated: None,
+                range: Range::from_tree(source, field.range()).0,
+                selection_range: Range::from_tree(source, name.range()).0,
+                children: None,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let indexes = descendants_of_kind(table, "index_declaration")
+        .into_iter()
+        .filter_map(|index| {
+            // Settings are left out of the name, as in `(id, created_at)`
+            let index_text = index.utf8_text(source).ok()?;
+            let name = index_text.split('[').next().unwrap_or(index_text).trim();
+            Some(DocumentSymbol {
+                name: name.to_string(),
+                detail: Some("index".to_string()),
+                kind: SymbolKind::Key,
+                tags: None,
+                deprecated: None,
+                range: Range::from_tree(source, index.range()).0,
+                selection_range: Range::from_tree(source, index.range()).0,
+                children: None,
+            })
+        });
+    columns.extend(indexes);
+
+    let mut table_symbol = symbol(source, table, SymbolKind::Struct, "Table");
+    table_symbol.detail = table
+        .child_by_field_name("alias")
+        .and_then(|alias| alias.utf8_text(source).ok())
+        .map(|alias| format!("as {}", alias));
+    table_symbol.children = Some(columns);
+    table_symbol
+}
+
+#[allow(deprecated)]
+fn enum_symbol(source: &[u8], enum_definition: Node) -> DocumentSymbol {
+    let values = enum_values(enum_definition)
+        .into_iter()
+        .filter_map(|value| {
+            let value_line = value.parent().unwrap_or(value);
+            Some(DocumentSymbol {
+                name: value.utf8_text(source).ok()?.to_string(),
+                detail: None,
+                kind: SymbolKind::EnumMember,
+                tags: None,
+                deprecated: None,
+                range: Range::from_tree(source, value_line.range()).0,
+                selection_range: Range::from_tree(source, value.range()).0,
+                children: None,
+            })
+        })
+        .collect();
+
+    let mut enum_symbol = symbol(source, enum_definition, SymbolKind::Enum, "Enum");
+    enum_symbol.children = Some(values);
+    enum_symbol
+}
+
+/// Refs are named after their relationships when they have no name of their own.
+fn ref_symbol(source: &[u8], ref_definition: Node) -> DocumentSymbol {
+    let descriptions = relationships(source, ref_definition)
+        .iter()
+        .map(|relationship| {
+            format!(
+                "{}.{} {} {}.{}",
+                relationship.left.table,
+                relationship.left.column_names(source).join(", "),
+                relationship.operator.utf8_text(source).unwrap_or_default(),
+                relationship.right.table,
+                relationship.right.column_names(source).join(", ")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("; ");
+
+    let mut ref_symbol = symbol(source, ref_definition, SymbolKind::Key, "Ref");
+    if ref_definition.child_by_field_name("name").is_some() {
+        ref_symbol.detail = Some(descriptions);
+    } else if !descriptions.is_empty() {
+        ref_symbol.name = descriptions;
+    }
+    ref_symbol
+}
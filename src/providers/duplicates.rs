@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use tower_lsp::lsp_types::Diagnostic;
+use tree_sitter::{Node, Tree};
+use url::Url;
+
+use super::diagnostics::related_diagnostic;
+use crate::navigation::{descendants_of_kind, enum_values};
+
+/// Reports tables, aliases, columns, enums and enum values defined more than once, each linking
+/// back to its first definition.
+pub(crate) fn duplicate_diagnostics(
+    source: &[u8],
+    tree: &Tree,
+    file_location: &Url,
+) -> Vec<Diagnostic> {
+    let root_node = tree.root_node();
+    let tables = descendants_of_kind(root_node, "table_definition");
+    let enums = descendants_of_kind(root_node, "enum_definition");
+
+    let table_names = tables
+        .iter()
+        .filter_map(|table| table.child_by_field_name("name"))
+        .collect::<Vec<_>>();
+    let aliases = tables
+        .iter()
+        .filter_map(|table| table.child_by_field_name("alias"))
+        .collect::<Vec<_>>();
+    let enum_names = enums
+        .iter()
+        .filter_map(|enum_definition| enum_definition.child_by_field_name("name"))
+        .collect::<Vec<_>>();
+
+    let mut duplicates = vec![];
+    for (duplicate, first) in find_duplicates(source, &table_names) {
+        let message = format!("duplicate table `{}`", text_of(source, duplicate));
+        duplicates.push((duplicate, first, message));
+    }
+    for (duplicate, first) in find_duplicates(source, &aliases) {
+        let message = format!("duplicate alias `{}`", text_of(source, duplicate));
+        duplicates.push((duplicate, first, message));
+    }
+    for (alias, table) in alias_collisions(source, &aliases, &table_names) {
+        let message = format!(
+            "alias `{}` clashes with the name of table `{}`",
+            text_of(source, alias),
+            text_of(source, table)
+        );
+        duplicates.push((alias, table, message));
+    }
+    for table in tables.iter() {
+        let columns = descendants_of_kind(*table, "field_declaration")
+            .into_iter()
+            .filter_map(|field| field.child_by_field_name("name"))
+            .collect::<Vec<_>>();
+
+        for (duplicate, first) in find_duplicates(source, &columns) {
+            let message = format!("duplicate column `{}`", text_of(source, duplicate));
+            duplicates.push((duplicate, first, message));
+        }
+    }
+    for (duplicate, first) in find_duplicates(source, &enum_names) {
+        let message = format!("duplicate enum `{}`", text_of(source, duplicate));
+        duplicates.push((duplicate, first, message));
+    }
+    for enum_definition in enums.iter() {
+        for (duplicate, first) in find_duplicates(source, &enum_values(*enum_definition)) {
+            let message = format!("duplicate enum value `{}`", text_of(source, duplicate));
+            duplicates.push((duplicate, first, message));
+        }
+    }
+
+    duplicates
+        .into_iter()
+        .map(|(duplicate, first, message)| {
+            related_diagnostic(source, duplicate, first, message, file_location)
+        })
+        .collect()
+}
+
+/// Pairs every node whose text was already seen with the first node holding that text.
+fn find_duplicates<'a>(source: &[u8], nodes: &[Node<'a>]) -> Vec<(Node<'a>, Node<'a>)> {
+    let mut first_seen: HashMap<&str, Node<'a>> = HashMap::new();
+    let mut duplicates = vec![];
+
+    for node in nodes.iter() {
+        let text = text_of(source, *node);
+        match first_seen.get(text) {
+            Some(first) => duplicates.push((*node, *first)),
+            None => {
+                let _ = first_seen.insert(text, *node);
+            }
+        }
+    }
+    duplicates
+}
+
+/// Pairs every alias with the definition of another table named just like it.
+fn alias_collisions<'a>(
+    source: &[u8],
+    aliases: &[Node<'a>],
+    table_names: &[Node<'a>],
+) -> Vec<(Node<'a>, Node<'a>)> {
+    aliases
+        .iter()
+        .filter_map(|alias| {
+            let own_table = alias.parent()?.child_by_field_name("name")?;
+            let colliding_table = table_names.iter().find(|table| {
+                table.start_byte() != own_table.start_byte()
+                    && text_of(source, **table) == text_of(source, *alias)
+            })?;
+            Some((*alias, *colliding_table))
+        })
+        .collect()
+}
+
+fn text_of<'a>(source: &'a [u8], node: Node) -> &'a str {
+    node.utf8_text(source).unwrap()
+}
@@ -0,0 +1,74 @@
+use std::collections::HashSet;
+
+use tower_lsp::lsp_types::{FoldingRange, FoldingRangeKind};
+use tree_sitter::{Node, Tree};
+
+use crate::navigation::descendants_of_kind;
+
+/// Folds every `{ ... }` block, such as tables, enums, indexes, projects, table groups, ref
+/// blocks and notes, along with multi-line strings and comments.
+pub fn folding_ranges(source: &str, tree: &Tree) -> Vec<FoldingRange> {
+    let mut ranges = vec![];
+    block_ranges(source.as_bytes(), tree.root_node(), &mut ranges);
+
+    // Blocks and their bodies start on the same line, only the outermost is kept
+    let mut folded_lines = HashSet::new();
+    ranges.retain(|range| folded_lines.insert(range.start_line));
+
+    ranges.extend(comment_ranges(source.as_bytes(), tree));
+    ranges
+}
+
+fn block_ranges(source: &[u8], node: Node, ranges: &mut Vec<FoldingRange>) {
+    let start_line = node.start_position().row as u32;
+    let end_line = node.end_position().row as u32;
+
+    if node.kind() != "project_file" && start_line < end_line {
+        let node_text = node.utf8_text(source).unwrap_or_default().trim_end();
+        if node_text.ends_with('}') || node.kind() == "string" {
+            ranges.push(FoldingRange {
+                start_line,
+                start_character: None,
+                end_line,
+                end_character: None,
+                kind: None,
+            });
+        }
+    }
+
+    for child in (0..node.child_count()).filter_map(|idx| node.child(idx)) {
+        block_ranges(source, child, ranges);
+    }
+}
+
+/// Multi-line comments, along with comments on consecutive lines, which are folded together.
+fn comment_ranges(source: &[u8], tree: &Tree) -> Vec<FoldingRange> {
+    let mut runs: Vec<(usize, usize)> = vec![];
+
+    for comment in descendants_of_kind(tree.root_node(), "comment") {
+        // Comments trailing some code are left with that line
+        let line_start = comment.start_byte() - comment.start_position().column;
+        let preceding = &source[line_start..comment.start_byte()];
+        if !preceding.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+
+        let start_line = comment.start_position().row;
+        let end_line = comment.end_position().row;
+        match runs.last_mut() {
+            Some((_, run_end)) if *run_end + 1 == start_line => *run_end = end_line,
+            _ => runs.push((start_line, end_line)),
+        }
+    }
+
+    runs.into_iter()
+        .filter(|(start_line, end_line)| start_line < end_line)
+        .map(|(start_line, end_line)| FoldingRange {
+            start_line: start_line as u32,
+            start_character: None,
+            end_line: end_line as u32,
+            end_character: None,
+            kind: Some(FoldingRangeKind::Comment),
+        })
+        .collect()
+}
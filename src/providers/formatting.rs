@@ -0,0 +1,257 @@
+use std::collections::HashSet;
+
+use tower_lsp::lsp_types::{
+    FormattingOptions, Position as LspPosition, Range as LspRange, TextEdit,
+};
+use tree_sitter::{Node, Tree};
+
+use crate::{
+    lexer::{lexemes, Lexeme},
+    navigation::{descendants_of_kind, field_type, parent_of_kind},
+    relationships::{relationships, CARDINALITY_OPERATORS},
+    wrappers::lsp_position,
+};
+
+/// Formats the whole document.
+///
+/// Columns of each table are aligned, settings and relationship operators get a canonical
+/// spacing, and blocks are indented. Comments and multi-line notes are kept byte-for-byte.
+pub fn format_document(
+    source: &str,
+    tree: &Tree,
+    options: &FormattingOptions,
+) -> Result<Vec<TextEdit>, String> {
+    if tree.root_node().has_error() {
+        return Err("Document has syntax errors, fix them before formatting.".to_string());
+    }
+
+    let indent_unit = if options.insert_spaces {
+        " ".repeat(options.tab_size as usize)
+    } else {
+        "\t".to_string()
+    };
+    let lines = source.split('\n').collect::<Vec<_>>();
+    let line_states = line_states(source);
+
+    let mut edits = vec![];
+    let mut formatted_rows = HashSet::new();
+
+    for table in descendants_of_kind(tree.root_node(), "table_definition") {
+        for (row, edit) in table_field_edits(source.as_bytes(), table, &line_states, &indent_unit) {
+            let _ = formatted_rows.insert(row);
+            edits.push(edit);
+        }
+    }
+
+    for (row, line) in lines.iter().enumerate() {
+        let state = match line_states.get(row) {
+            Some(state) => *state,
+            None => continue,
+        };
+        let content = line.trim_start();
+        if formatted_rows.contains(&row) || state.inside_literal || content.trim().is_empty() {
+            continue;
+        }
+
+        let depth = if content.starts_with('}') {
+            state.depth.saturating_sub(1)
+        } else {
+            state.depth
+        };
+        let leading_whitespace = &line[..line.len() - content.len()];
+        let indentation = indent_unit.repeat(depth);
+        if leading_whitespace != indentation {
+            edits.push(TextEdit {
+                range: LspRange::new(
+                    LspPosition::new(row as u32, 0),
+                    LspPosition::new(row as u32, leading_whitespace.encode_utf16().count() as u32),
+                ),
+                new_text: indentation,
+            });
+        }
+    }
+
+    edits.extend(relationship_operator_edits(source.as_bytes(), tree));
+    Ok(edits)
+}
+
+/// Formats only the lines touched by the range, although columns are still aligned against the
+/// whole table.
+pub fn format_range(
+    source: &str,
+    tree: &Tree,
+    options: &FormattingOptions,
+    range: LspRange,
+) -> Result<Vec<TextEdit>, String> {
+    Ok(format_document(source, tree, options)?
+        .into_iter()
+        .filter(|edit| {
+            range.start.line <= edit.range.start.line && edit.range.end.line <= range.end.line
+        })
+        .collect())
+}
+
+/// Rewrites every single line column of the table, aligning names, types and settings.
+fn table_field_edits(
+    source: &[u8],
+    table: Node,
+    line_states: &[LineState],
+    indent_unit: &str,
+) -> Vec<(usize, TextEdit)> {
+    let fields = descendants_of_kind(table, "field_declaration")
+        .into_iter()
+        // Columns spanning multiple lines, such as those with multi-line notes, are left as is
+        .filter(|field| field.start_position().row == field.end_position().row)
+        .filter(|field| parent_of_kind(*field, "indexes_definition").is_none())
+        .filter(|field| {
+            let line_start = field.start_byte() - field.start_position().column;
+            source[line_start..field.start_byte()]
+                .iter()
+                .all(u8::is_ascii_whitespace)
+        })
+        .filter_map(|field| Some((field, field_parts(source, field)?)))
+        .collect::<Vec<_>>();
+
+    let name_width = fields
+        .iter()
+        .map(|(_, (name, _, _))| name.chars().count())
+        .max()
+        .unwrap_or_default();
+    let type_width = fields
+        .iter()
+        .map(|(_, (_, field_type, _))| field_type.chars().count())
+        .max()
+        .unwrap_or_default();
+
+    fields
+        .into_iter()
+        .filter_map(|(field, (name, field_type, settings))| {
+            let row = field.start_position().row;
+            let indentation = indent_unit.repeat(line_states.get(row)?.depth);
+
+            let mut new_text = format!("{}{:<width$}", indentation, name, width = name_width);
+            match settings {
+                Some(settings) => new_text.push_str(&format!(
+                    " {:<width$} {}",
+                    field_type,
+                    settings,
+                    width = type_width
+                )),
+                None => new_text.push_str(&format!(" {}", field_type)),
+            }
+
+            let line_start = field.start_byte() - field.start_position().column;
+            if &source[line_start..field.end_byte()] == new_text.as_bytes() {
+                return None;
+            }
+
+            let range = LspRange::new(
+                LspPosition::new(row as u32, 0),
+                lsp_position(source, field.end_byte()),
+            );
+            Some((row, TextEdit { range, new_text }))
+        })
+        .collect()
+}
+
+/// Name, type and normalized settings of a column.
+fn field_parts(source: &[u8], field: Node) -> Option<(String, String, Option<String>)> {
+    let name = field.child_by_field_name("name")?.utf8_text(source).ok()?;
+    let field_type = field_type(field)?.utf8_text(source).ok()?;
+
+    let settings = descendants_of_kind(field, "field_attribute_list")
+        .first()
+        .map(|attributes| {
+            let settings = (0..attributes.named_child_count())
+                .filter_map(|idx| attributes.named_child(idx))
+                .filter_map(|attribute| attribute.utf8_text(source).ok())
+                .map(normalize_setting)
+                .collect::<Vec<_>>();
+            format!("[{}]", settings.join(", "))
+        });
+
+    Some((name.to_string(), field_type.to_string(), settings))
+}
+
+/// Spaces a setting as `key: value`, and relationships as `ref: > table.column`. Values holding
+/// quotes are kept as written.
+fn normalize_setting(setting: &str) -> String {
+    let setting = setting.trim();
+    let separator = match setting.find(':') {
+        Some(separator) => separator,
+        None => return setting.split_whitespace().collect::<Vec<_>>().join(" "),
+    };
+
+    let key = setting[..separator].trim();
+    let value = setting[separator + 1..].trim();
+    if value.contains(|c| c == '\'' || c == '"' || c == '`') {
+        return format!("{}: {}", key, value);
+    }
+
+    let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+    let operator = CARDINALITY_OPERATORS
+        .iter()
+        .find(|operator| value.starts_with(*operator));
+    match operator {
+        Some(operator) if key.eq_ignore_ascii_case("ref") => {
+            format!("{}: {} {}", key, operator, value[operator.len()..].trim())
+        }
+        _ => format!("{}: {}", key, value),
+    }
+}
+
+/// Puts a single space around the operator of standalone relationships.
+fn relationship_operator_edits(source: &[u8], tree: &Tree) -> Vec<TextEdit> {
+    relationships(source, tree.root_node())
+        .into_iter()
+        .filter(|relationship| relationship.inline_on.is_none())
+        .filter_map(|relationship| {
+            let left = relationship.left.table_field?;
+            let right = relationship.right.table_field?;
+            if left.end_position().row != right.start_position().row {
+                return None;
+            }
+
+            let gap = &source[left.end_byte()..right.start_byte()];
+            let operator = relationship.operator.utf8_text(source).ok()?;
+            let new_text = format!(" {} ", operator);
+            if gap == new_text.as_bytes() {
+                return None;
+            }
+
+            Some(TextEdit {
+                range: LspRange::new(
+                    lsp_position(source, left.end_byte()),
+                    lsp_position(source, right.start_byte()),
+                ),
+                new_text,
+            })
+        })
+        .collect()
+}
+
+/// Block depth at the start of each line, and whether the line starts inside a multi-line
+/// string or block comment, which must be kept byte-for-byte.
+#[derive(Debug, Default, Clone, Copy)]
+struct LineState {
+    depth: usize,
+    inside_literal: bool,
+}
+
+fn line_states(source: &str) -> Vec<LineState> {
+    let mut states = vec![LineState::default()];
+    let mut depth = 0_usize;
+
+    for (byte, lexeme) in source.bytes().zip(lexemes(source)) {
+        match (byte, lexeme) {
+            (b'{', Lexeme::Code) => depth += 1,
+            (b'}', Lexeme::Code) => depth = depth.saturating_sub(1),
+            (b'\n', lexeme) => states.push(LineState {
+                depth,
+                inside_literal: lexeme != Lexeme::Code,
+            }),
+            _ => {}
+        }
+    }
+    states
+}
@@ -0,0 +1,63 @@
+use tower_lsp::lsp_types::{GotoDefinitionResponse, Location, Position as LspPosition};
+use tree_sitter::{Node, Tree};
+use url::Url;
+
+use crate::{
+    navigation::{field_type, identifier_at_point, parent_of_kind},
+    wrappers::{Point, Range},
+    IdentifiersMap,
+};
+
+/// Resolves the definition of the identifier under the cursor.
+///
+/// Tables and aliases on relationships lead to the table definition, fields on relationships to
+/// their declaration, and enum typed columns to the enum definition.
+pub fn goto_definition(
+    source: &str,
+    tree: &Tree,
+    identifiers: &IdentifiersMap,
+    edit_position: LspPosition,
+    file_location: Url,
+) -> Option<GotoDefinitionResponse> {
+    let root_node = tree.root_node();
+    let current_node =
+        identifier_at_point(root_node, Point::from_lsp(source.as_bytes(), edit_position))?;
+
+    let definition = definition_range(source.as_bytes(), current_node, identifiers)?;
+    Some(GotoDefinitionResponse::Scalar(Location::new(
+        file_location,
+        definition.0,
+    )))
+}
+
+fn definition_range(source: &[u8], node: Node, identifiers: &IdentifiersMap) -> Option<Range> {
+    let node_text = node.utf8_text(source).ok()?;
+
+    if let Some(table_field) = parent_of_kind(node, "table_field") {
+        let table = table_field.child_by_field_name("table")?;
+        let table_name = table.utf8_text(source).ok()?;
+
+        if table.start_byte() == node.start_byte() {
+            return identifiers.table_definition(table_name);
+        }
+        return identifiers
+            .field_of_table(table_name, node_text)
+            .map(|field| field.range);
+    }
+
+    // An alias on its own definition leads to the table name
+    if node.parent()?.kind() == "table_definition" {
+        return identifiers.table_definition(node_text);
+    }
+
+    // Otherwise we might be at the type of a field declaration, which can be an enum
+    let field_declaration = parent_of_kind(node, "field_declaration")?;
+    let field_type = field_type(field_declaration)?;
+    let is_field_type =
+        field_type.start_byte() <= node.start_byte() && node.end_byte() <= field_type.end_byte();
+
+    if !is_field_type {
+        return None;
+    }
+    identifiers.enum_definition(node_text)
+}
@@ -0,0 +1,123 @@
+use tower_lsp::lsp_types::{
+    Hover, HoverContents, MarkupContent, MarkupKind, Position as LspPosition,
+};
+use tree_sitter::{Node, Tree};
+
+use super::rename::{resolve_rename, RenameRules};
+use crate::{relationships::relationships, wrappers::Range, FieldInfo, IdentifiersMap};
+
+/// Shows a Markdown card describing the table, column or enum under the cursor.
+pub fn hover(
+    source: &str,
+    tree: &Tree,
+    identifiers: &IdentifiersMap,
+    edit_position: LspPosition,
+) -> Option<Hover> {
+    let source = source.as_bytes();
+    let root_node = tree.root_node();
+    let (node, rule) = resolve_rename(source, root_node, identifiers, edit_position).ok()?;
+
+    let card = match rule {
+        RenameRules::Table(table_or_alias) => {
+            table_card(source, root_node, identifiers, &table_or_alias)?
+        }
+        RenameRules::Field { table, field } => {
+            field_card(&table, identifiers.field_of_table(&table, &field)?)
+        }
+        RenameRules::Enum(enum_name) => enum_card(identifiers, &enum_name)?,
+    };
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: card,
+        }),
+        range: Some(Range::from_tree(source, node.range()).0),
+    })
+}
+
+fn table_card(
+    source: &[u8],
+    root_node: Node,
+    identifiers: &IdentifiersMap,
+    table_or_alias: &str,
+) -> Option<String> {
+    let table_name = identifiers.table_name_of(table_or_alias);
+    let table = identifiers.table_info(&table_name)?;
+
+    let mut card = format!("**Table** `{}`", table.text_name);
+    if let Some(alias) = &table.alias {
+        card.push_str(&format!(" as `{}`", alias));
+    }
+    if let Some(note) = &table.note {
+        card.push_str(&format!("\n\n{}", note));
+    }
+
+    let fields = identifiers.fields_of_table(&table_name).unwrap_or_default();
+    if !fields.is_empty() {
+        card.push_str("\n\n| Column | Type | Settings |\n|---|---|---|");
+        for field in fields.iter() {
+            card.push_str(&format!(
+                "\n| `{}` | `{}` | {} |",
+                field.text_name,
+                field.r#type,
+                field.settings.join(", ")
+            ));
+        }
+    }
+
+    let mut outgoing = vec![];
+    let mut incoming = vec![];
+    for relationship in relationships(source, root_node) {
+        let (left, right) = (&relationship.left, &relationship.right);
+        let description = format!(
+            "`{}.{}` {} `{}.{}`",
+            left.table,
+            left.column_names(source).join(", "),
+            relationship.operator.utf8_text(source).ok()?,
+            right.table,
+            right.column_names(source).join(", ")
+        );
+
+        if identifiers.table_name_of(&left.table) == table_name {
+            outgoing.push(description);
+        } else if identifiers.table_name_of(&right.table) == table_name {
+            incoming.push(description);
+        }
+    }
+
+    for (title, descriptions) in &[("Outgoing", outgoing), ("Incoming", incoming)] {
+        if descriptions.is_empty() {
+            continue;
+        }
+        card.push_str(&format!("\n\n**{} relationships**\n", title));
+        for description in descriptions.iter() {
+            card.push_str(&format!("\n- {}", description));
+        }
+    }
+    Some(card)
+}
+
+fn field_card(table_name: &str, field: &FieldInfo) -> String {
+    let mut card = format!(
+        "**Column** `{}.{}`\n\n`{}`",
+        table_name, field.text_name, field.r#type
+    );
+    if !field.settings.is_empty() {
+        card.push_str(&format!(" [{}]", field.settings.join(", ")));
+    }
+    if let Some(note) = &field.note {
+        card.push_str(&format!("\n\n{}", note));
+    }
+    card
+}
+
+fn enum_card(identifiers: &IdentifiersMap, enum_name: &str) -> Option<String> {
+    let enum_info = identifiers.enum_info(enum_name)?;
+
+    let mut card = format!("**Enum** `{}`\n", enum_info.text_name);
+    for value in enum_info.values.iter() {
+        card.push_str(&format!("\n- `{}`", value));
+    }
+    Some(card)
+}
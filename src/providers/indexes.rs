@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use tower_lsp::lsp_types::Diagnostic;
+use tree_sitter::{Node, Tree};
+use url::Url;
+
+use super::diagnostics::related_diagnostic;
+use crate::navigation::descendants_of_kind;
+
+/// Reports index names used more than once, and tables with more than one primary key, either
+/// set on a column or on an index.
+pub(crate) fn index_diagnostics(
+    source: &[u8],
+    tree: &Tree,
+    file_location: &Url,
+) -> Vec<Diagnostic> {
+    let root_node = tree.root_node();
+    let mut diagnostics = vec![];
+
+    let mut first_index_names: HashMap<String, Node> = HashMap::new();
+    for setting in settings_of_kind(root_node, "index_attribute_list") {
+        let index_name = match setting_value(source, setting, "name") {
+            Some(index_name) => index_name,
+            None => continue,
+        };
+
+        match first_index_names.get(&index_name) {
+            Some(first) => diagnostics.push(related_diagnostic(
+                source,
+                setting,
+                *first,
+                format!("duplicate index name `{}`", index_name),
+                file_location,
+            )),
+            None => {
+                let _ = first_index_names.insert(index_name, setting);
+            }
+        }
+    }
+
+    for table in descendants_of_kind(root_node, "table_definition") {
+        let table_name = table
+            .child_by_field_name("name")
+            .and_then(|name| name.utf8_text(source).ok())
+            .unwrap_or_default();
+
+        let mut primary_keys = ["field_attribute_list", "index_attribute_list"]
+            .iter()
+            .flat_map(|kind| settings_of_kind(table, kind))
+            .filter(|setting| is_primary_key(source, *setting))
+            .collect::<Vec<_>>();
+        primary_keys.sort_by_key(|setting| setting.start_byte());
+
+        if let Some((first, others)) = primary_keys.split_first() {
+            for primary_key in others {
+                diagnostics.push(related_diagnostic(
+                    source,
+                    *primary_key,
+                    *first,
+                    format!("table `{}` has more than one primary key", table_name),
+                    file_location,
+                ));
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Every setting of the attribute lists of the given kind, as in `pk` or `name: 'by_date'`.
+fn settings_of_kind<'a>(node: Node<'a>, kind: &str) -> Vec<Node<'a>> {
+    descendants_of_kind(node, kind)
+        .into_iter()
+        .flat_map(|attributes| {
+            (0..attributes.named_child_count()).filter_map(move |idx| attributes.named_child(idx))
+        })
+        .collect()
+}
+
+/// Value of the setting if it has the given key, without its quotes.
+fn setting_value(source: &[u8], setting: Node, key: &str) -> Option<String> {
+    let setting_text = setting.utf8_text(source).ok()?;
+    let separator = setting_text.find(':')?;
+    if !setting_text[..separator].trim().eq_ignore_ascii_case(key) {
+        return None;
+    }
+
+    let value = setting_text[separator + 1..]
+        .trim()
+        .trim_matches(|c| c == '\'' || c == '"');
+    Some(value.to_string())
+}
+
+fn is_primary_key(source: &[u8], setting: Node) -> bool {
+    setting.utf8_text(source).map_or(false, |setting| {
+        let setting = setting.split_whitespace().collect::<Vec<_>>().join(" ");
+        setting.eq_ignore_ascii_case("pk") || setting.eq_ignore_ascii_case("primary key")
+    })
+}
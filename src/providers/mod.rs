@@ -1,6 +1,35 @@
+mod code_action;
+mod compatibility;
 mod completion;
+mod diagnostics;
+mod document_symbol;
+mod duplicates;
+mod folding_range;
+mod formatting;
 mod goto_definition;
+mod hover;
+mod indexes;
+mod ref_conversion;
+mod references;
+mod relationship_settings;
 mod rename;
+mod semantic_tokens;
+mod validation;
+mod workspace_symbol;
 
+pub use code_action::code_actions;
+pub use compatibility::TypeCompatibility;
 pub use completion::complete_at_point;
-pub use rename::rename;
+pub use diagnostics::syntax_diagnostics;
+pub use document_symbol::document_symbols;
+pub use folding_range::folding_ranges;
+pub use formatting::{format_document, format_range};
+pub use goto_definition::goto_definition;
+pub use hover::hover;
+pub use references::references;
+pub use rename::{prepare_rename, rename};
+pub use semantic_tokens::{semantic_tokens, semantic_tokens_legend};
+pub use validation::semantic_diagnostics;
+pub use workspace_symbol::{
+    document_workspace_symbols, file_workspace_symbols, rank_workspace_symbols,
+};
@@ -0,0 +1,380 @@
+//! Refactors turning inline relationships, as in `user_id int [ref: > users.id]`, into standalone
+//! ones, as in `Ref: orders.user_id > users.id`, and back.
+//!
+//! Inline relationships can't hold settings, so standalone ones with settings such as
+//! `[delete: cascade]` are never turned inline, which would drop them. Composite relationships
+//! are kept standalone for the same reason.
+
+use std::collections::HashMap;
+
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, Position as LspPosition, Range as LspRange,
+    TextEdit, WorkspaceEdit,
+};
+use tree_sitter::Node;
+use url::Url;
+
+use super::code_action::overlaps;
+use crate::{
+    navigation::{descendants_of_kind, field_type, parent_of_kind},
+    relationships::{relationships, Relationship},
+    wrappers::{lsp_position, Range},
+    IdentifiersMap,
+};
+
+/// Conversions of the relationships under the range, and of every relationship of the document.
+pub(crate) fn ref_conversion_actions(
+    source: &[u8],
+    root_node: Node,
+    identifiers: &IdentifiersMap,
+    range: LspRange,
+    file_location: &Url,
+) -> Vec<CodeActionOrCommand> {
+    let relationships = relationships(source, root_node);
+    let inline = relationships
+        .iter()
+        .filter(|relationship| relationship.inline_on.is_some())
+        .cloned()
+        .collect::<Vec<_>>();
+    let convertible_standalone = relationships
+        .iter()
+        .filter(|relationship| can_be_inline(source, root_node, identifiers, relationship))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let under_range =
+        |relationship: &&Relationship| overlaps(relationship_range(source, relationship), range);
+    let mut actions = vec![];
+
+    for relationship in inline.iter().filter(under_range) {
+        let edits = to_standalone_edits(source, root_node, &[relationship.clone()]);
+        actions.push(refactor("Convert to standalone Ref", edits, file_location));
+    }
+    for relationship in convertible_standalone.iter().filter(under_range) {
+        let edits = to_inline_edits(source, root_node, identifiers, &[relationship.clone()]);
+        actions.push(refactor("Convert to inline ref", edits, file_location));
+    }
+
+    if actions.is_empty() {
+        return actions;
+    }
+    if inline.len() > 1 {
+        let edits = to_standalone_edits(source, root_node, &inline);
+        actions.push(refactor(
+            "Convert all inline refs to standalone Refs",
+            edits,
+            file_location,
+        ));
+    }
+    if convertible_standalone.len() > 1 {
+        let edits = to_inline_edits(source, root_node, identifiers, &convertible_standalone);
+        actions.push(refactor(
+            "Convert all standalone Refs to inline refs",
+            edits,
+            file_location,
+        ));
+    }
+    actions
+}
+
+fn refactor(title: &str, edits: Vec<TextEdit>, file_location: &Url) -> CodeActionOrCommand {
+    let mut changes = HashMap::new();
+    let _ = changes.insert(file_location.clone(), edits);
+
+    CodeActionOrCommand::CodeAction(CodeAction {
+        title: title.to_string(),
+        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+        diagnostics: None,
+        edit: Some(WorkspaceEdit {
+            changes: Some(changes),
+            document_changes: None,
+            change_annotations: None,
+        }),
+        command: None,
+        is_preferred: None,
+        disabled: None,
+        data: None,
+    })
+}
+
+fn relationship_range(source: &[u8], relationship: &Relationship) -> LspRange {
+    let start = relationship
+        .inline_on
+        .or(relationship.left.table_field)
+        .unwrap_or(relationship.operator);
+    let end = relationship
+        .right
+        .table_field
+        .unwrap_or(relationship.operator);
+
+    LspRange::new(
+        Range::from_tree(source, start.range()).0.start,
+        Range::from_tree(source, end.range()).0.end,
+    )
+}
+
+/// Standalone relationships between single columns, without settings, whose left column is
+/// declared on the document.
+fn can_be_inline(
+    source: &[u8],
+    root_node: Node,
+    identifiers: &IdentifiersMap,
+    relationship: &Relationship,
+) -> bool {
+    if relationship.inline_on.is_some()
+        || relationship.left.columns.len() != 1
+        || relationship.right.columns.len() != 1
+    {
+        return false;
+    }
+
+    let has_settings = relationship.settings(source).is_some();
+    !has_settings && inline_target(source, root_node, identifiers, relationship).is_some()
+}
+
+/// Declaration of the column the relationship is set on, once turned inline.
+fn inline_target<'a>(
+    source: &[u8],
+    root_node: Node<'a>,
+    identifiers: &IdentifiersMap,
+    relationship: &Relationship,
+) -> Option<Node<'a>> {
+    let table_name = identifiers.table_name_of(&relationship.left.table);
+    let column_name = *relationship.left.column_names(source).first()?;
+
+    let table = descendants_of_kind(root_node, "table_definition")
+        .into_iter()
+        .find(|table| {
+            table
+                .child_by_field_name("name")
+                .and_then(|name| name.utf8_text(source).ok())
+                == Some(table_name.as_str())
+        })?;
+
+    descendants_of_kind(table, "field_declaration")
+        .into_iter()
+        .find(|field| {
+            field
+                .child_by_field_name("name")
+                .and_then(|name| name.utf8_text(source).ok())
+                == Some(column_name)
+        })
+}
+
+/// Removes the `ref:` settings from their columns, and appends the matching `Ref:` lines at the
+/// end of the document.
+fn to_standalone_edits(
+    source: &[u8],
+    root_node: Node,
+    relationships: &[Relationship],
+) -> Vec<TextEdit> {
+    let mut removed_per_list: HashMap<usize, (Node, Vec<Node>)> = HashMap::new();
+    let mut ref_lines = vec![];
+
+    for relationship in relationships {
+        let setting = match setting_of_operator(relationship.operator) {
+            Some(setting) => setting,
+            None => continue,
+        };
+        let attribute_list = match setting.parent() {
+            Some(attribute_list) => attribute_list,
+            None => continue,
+        };
+        let (column, right) = match (
+            relationship.left.column_names(source).first(),
+            relationship.right.table_field,
+        ) {
+            (Some(column), Some(right)) => (*column, right),
+            _ => continue,
+        };
+
+        ref_lines.push(format!(
+            "Ref: {}.{} {} {}",
+            relationship.left.table,
+            column,
+            relationship.operator.utf8_text(source).unwrap_or_default(),
+            right.utf8_text(source).unwrap_or_default()
+        ));
+        removed_per_list
+            .entry(attribute_list.start_byte())
+            .or_insert_with(|| (attribute_list, vec![]))
+            .1
+            .push(setting);
+    }
+
+    let mut edits = removed_per_list
+        .values()
+        .filter_map(|(attribute_list, removed)| {
+            let remaining = (0..attribute_list.named_child_count())
+                .filter_map(|idx| attribute_list.named_child(idx))
+                .filter(|setting| !removed.contains(setting))
+                .filter_map(|setting| setting.utf8_text(source).ok())
+                .map(str::trim)
+                .collect::<Vec<_>>();
+
+            if !remaining.is_empty() {
+                return Some(TextEdit {
+                    range: Range::from_tree(source, attribute_list.range()).0,
+                    new_text: format!("[{}]", remaining.join(", ")),
+                });
+            }
+
+            // The whole list goes away, along with the space before it
+            let field_declaration = parent_of_kind(*attribute_list, "field_declaration")?;
+            let list_start = field_type(field_declaration)
+                .map_or(attribute_list.start_byte(), |field_type| {
+                    field_type.end_byte()
+                });
+            Some(TextEdit {
+                range: LspRange::new(
+                    lsp_position(source, list_start),
+                    Range::from_tree(source, attribute_list.range()).0.end,
+                ),
+                new_text: String::new(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    if !ref_lines.is_empty() {
+        let end = lsp_position(source, root_node.end_byte());
+        let separator = if source.ends_with(b"\n") {
+            "\n"
+        } else {
+            "\n\n"
+        };
+        edits.push(TextEdit {
+            range: LspRange::new(end, end),
+            new_text: format!("{}{}\n", separator, ref_lines.join("\n")),
+        });
+    }
+    edits
+}
+
+/// Adds a `ref:` setting to the left column of each relationship, and removes the standalone
+/// relationships, along with their `Ref` blocks once empty.
+fn to_inline_edits(
+    source: &[u8],
+    root_node: Node,
+    identifiers: &IdentifiersMap,
+    relationships: &[Relationship],
+) -> Vec<TextEdit> {
+    let mut added_per_field: HashMap<usize, (Node, Vec<String>)> = HashMap::new();
+    let mut converted_per_ref: HashMap<usize, (Node, Vec<&Relationship>)> = HashMap::new();
+
+    for relationship in relationships {
+        let field = match inline_target(source, root_node, identifiers, relationship) {
+            Some(field) => field,
+            None => continue,
+        };
+        let (right, ref_definition) = match (
+            relationship.right.table_field,
+            parent_of_kind(relationship.operator, "ref_definition"),
+        ) {
+            (Some(right), Some(ref_definition)) => (right, ref_definition),
+            _ => continue,
+        };
+
+        let setting = format!(
+            "ref: {} {}",
+            relationship.operator.utf8_text(source).unwrap_or_default(),
+            right.utf8_text(source).unwrap_or_default()
+        );
+        added_per_field
+            .entry(field.start_byte())
+            .or_insert_with(|| (field, vec![]))
+            .1
+            .push(setting);
+        converted_per_ref
+            .entry(ref_definition.start_byte())
+            .or_insert_with(|| (ref_definition, vec![]))
+            .1
+            .push(relationship);
+    }
+
+    let mut edits = added_per_field
+        .values()
+        .map(
+            |(field, added)| match descendants_of_kind(*field, "field_attribute_list").first() {
+                Some(attribute_list) => {
+                    let settings = (0..attribute_list.named_child_count())
+                        .filter_map(|idx| attribute_list.named_child(idx))
+                        .filter_map(|setting| setting.utf8_text(source).ok())
+                        .map(|setting| setting.trim().to_string())
+                        .chain(added.iter().cloned())
+                        .collect::<Vec<_>>();
+                    TextEdit {
+                        range: Range::from_tree(source, attribute_list.range()).0,
+                        new_text: format!("[{}]", settings.join(", ")),
+                    }
+                }
+                None => {
+                    let end = Range::from_tree(source, field.range()).0.end;
+                    TextEdit {
+                        range: LspRange::new(end, end),
+                        new_text: format!(" [{}]", added.join(", ")),
+                    }
+                }
+            },
+        )
+        .collect::<Vec<_>>();
+
+    for (ref_definition, converted) in converted_per_ref.values() {
+        let relationship_count = descendants_of_kind(*ref_definition, "cardinality_op").len();
+        if relationship_count == converted.len() {
+            edits.push(removal(source, *ref_definition, *ref_definition));
+            continue;
+        }
+
+        // Only some relationships of a `Ref { }` block are converted, the block is kept
+        for relationship in converted {
+            if let (Some(left), Some(right)) = (
+                relationship.left.table_field,
+                relationship.right.table_field,
+            ) {
+                edits.push(removal(source, left, right));
+            }
+        }
+    }
+    edits
+}
+
+/// The setting holding the operator of an inline relationship, as in `ref: > users.id`.
+fn setting_of_operator(operator: Node) -> Option<Node> {
+    let mut current_node = operator;
+    while let Some(parent) = current_node.parent() {
+        if parent.kind() == "field_attribute_list" {
+            return Some(current_node);
+        }
+        current_node = parent;
+    }
+    None
+}
+
+/// Removes the text from the start of the first node to the end of the last one. Their lines go
+/// away too, unless they hold something else, such as the braces of a one-line `Ref { }` block.
+fn removal(source: &[u8], first: Node, last: Node) -> TextEdit {
+    let line_start = first.start_byte() - first.start_position().column;
+    let line_end = source[last.end_byte()..]
+        .iter()
+        .position(|byte| *byte == b'\n')
+        .map_or(source.len(), |offset| last.end_byte() + offset);
+    let is_blank = |text: &[u8]| text.iter().all(u8::is_ascii_whitespace);
+
+    let range = if is_blank(&source[line_start..first.start_byte()])
+        && is_blank(&source[last.end_byte()..line_end])
+    {
+        LspRange::new(
+            LspPosition::new(first.start_position().row as u32, 0),
+            LspPosition::new(last.end_position().row as u32 + 1, 0),
+        )
+    } else {
+        LspRange::new(
+            Range::from_tree(source, first.range()).0.start,
+            Range::from_tree(source, last.range()).0.end,
+        )
+    };
+    TextEdit {
+        range,
+        new_text: String::new(),
+    }
+}
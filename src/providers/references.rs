@@ -0,0 +1,46 @@
+use tower_lsp::lsp_types::{Location, Position as LspPosition};
+use tree_sitter::Tree;
+use url::Url;
+
+use super::rename::{resolve_rename, RenameRules};
+use crate::IdentifiersMap;
+
+/// Lists every location where the table, field or enum under the cursor is used.
+///
+/// Tables are also looked up through their aliases, since both stand for the same table.
+pub fn references(
+    source: &str,
+    tree: &Tree,
+    identifiers: &IdentifiersMap,
+    edit_position: LspPosition,
+    include_declaration: bool,
+    file_location: Url,
+) -> Option<Vec<Location>> {
+    let source = source.as_bytes();
+    let root_node = tree.root_node();
+    let (_, rule) = resolve_rename(source, root_node, identifiers, edit_position).ok()?;
+
+    let rules: Vec<RenameRules> = match rule {
+        RenameRules::Table(table_or_alias) => identifiers
+            .names_of_table(&identifiers.table_name_of(&table_or_alias))
+            .into_iter()
+            .map(RenameRules::Table)
+            .collect(),
+        rule => vec![rule],
+    };
+
+    let mut ranges = vec![];
+    for rule in rules.iter() {
+        if include_declaration {
+            ranges.extend(rule.declarations(source, root_node, identifiers));
+        }
+        ranges.extend(rule.usages(source, root_node, identifiers));
+    }
+
+    Some(
+        ranges
+            .into_iter()
+            .map(|range| Location::new(file_location.clone(), range.0))
+            .collect(),
+    )
+}
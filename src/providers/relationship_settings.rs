@@ -0,0 +1,117 @@
+use tower_lsp::lsp_types::Diagnostic;
+use tree_sitter::Tree;
+
+use super::{
+    completion::{REFERENTIAL_ACTIONS, RELATIONSHIP_SETTINGS},
+    diagnostics::error_diagnostic,
+    validation::{suggestions, with_suggestions},
+};
+use crate::{
+    relationships::{relationships, Relationship, RelationshipSide},
+    IdentifiersMap,
+};
+
+/// Reports unknown settings and actions on standalone relationships, and `set null` actions on
+/// columns which can't be null.
+pub(crate) fn relationship_setting_diagnostics(
+    source: &[u8],
+    tree: &Tree,
+    identifiers: &IdentifiersMap,
+) -> Vec<Diagnostic> {
+    let keys = RELATIONSHIP_SETTINGS
+        .iter()
+        .map(|setting| setting.trim_end_matches(':').to_string())
+        .collect::<Vec<_>>();
+    let actions = REFERENTIAL_ACTIONS
+        .iter()
+        .map(|action| action.to_string())
+        .collect::<Vec<_>>();
+    let mut diagnostics = vec![];
+
+    for relationship in relationships(source, tree.root_node()) {
+        for (range, setting) in relationship.settings(source).unwrap_or_default() {
+            let mut key_value = setting.splitn(2, ':');
+            let key = key_value.next().unwrap_or_default().trim().to_lowercase();
+            let value = key_value
+                .next()
+                .unwrap_or_default()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .to_lowercase();
+
+            if !keys.contains(&key) {
+                let message = with_suggestions(
+                    format!("unknown relationship setting `{}`", key),
+                    &suggestions(&key, &keys),
+                );
+                diagnostics.push(error_diagnostic(range, message));
+                continue;
+            }
+            if key == "color" {
+                continue;
+            }
+
+            if !actions.contains(&value) {
+                let message = with_suggestions(
+                    format!("unknown action `{}` for `{}`", value, key),
+                    &suggestions(&value, &actions),
+                );
+                diagnostics.push(error_diagnostic(range, message));
+                continue;
+            }
+
+            if value == "set null" {
+                let not_null_columns = referencing_side(source, &relationship)
+                    .into_iter()
+                    .flat_map(|side| not_null_columns(source, identifiers, side))
+                    .collect::<Vec<_>>();
+                for column in not_null_columns {
+                    let message = format!(
+                        "`{}: set null` can't be applied, `{}` is not null",
+                        key, column
+                    );
+                    diagnostics.push(error_diagnostic(range, message));
+                }
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Side holding the foreign key, which is the many side, or the left one on one-to-one
+/// relationships. Many-to-many relationships have none.
+fn referencing_side<'a, 'b>(
+    source: &[u8],
+    relationship: &'b Relationship<'a>,
+) -> Option<&'b RelationshipSide<'a>> {
+    match relationship.operator.utf8_text(source).ok()? {
+        ">" | "-" => Some(&relationship.left),
+        "<" => Some(&relationship.right),
+        _ => None,
+    }
+}
+
+/// Columns of the side, as `table.column`, set as `not null`.
+fn not_null_columns(
+    source: &[u8],
+    identifiers: &IdentifiersMap,
+    side: &RelationshipSide,
+) -> Vec<String> {
+    let table_name = identifiers.table_name_of(&side.table);
+
+    side.column_names(source)
+        .into_iter()
+        .filter(|column| {
+            identifiers
+                .field_of_table(&table_name, column)
+                .map_or(false, |field| {
+                    field.settings.iter().any(|setting| {
+                        let setting = setting.split_whitespace().collect::<Vec<_>>().join(" ");
+                        setting.eq_ignore_ascii_case("not null")
+                    })
+                })
+        })
+        .map(|column| format!("{}.{}", side.table, column))
+        .collect()
+}
@@ -1,187 +1,386 @@
 use std::collections::HashMap;
 
-use log::info;
-use tower_lsp::lsp_types::{Position as LspPosition, TextEdit, WorkspaceEdit};
-use tree_sitter::{Node, Query, QueryCursor};
+use tower_lsp::lsp_types::{
+    Position as LspPosition, PrepareRenameResponse, TextEdit, WorkspaceEdit,
+};
+use tree_sitter::{Node, Tree};
 use url::Url;
 
+use super::completion::{KEYWORDS, PRIMITIVE_TYPES};
 use crate::{
-    file::parse_file,
-    find_location_on_ast, populate_identifiers,
+    navigation::{
+        descendants_of_kind, field_type, identifier_at_point, node_parent_identifier,
+        parent_of_kind, table_field_columns,
+    },
     wrappers::{Point, Range},
-    LANGUAGE,
+    IdentifiersMap,
 };
 
-const RENAME_TABLE_RULE: &[&str; 2] = &[
-    "((table_definition name: (identifier) @table_name) (#eq? @table_name \"{}\"))",
-    "((table_field table: (identifier) @table_name )(#eq? @table_name \"{}\"))",
-];
-
-const RENAME_FIELD_RULE: &[&str; 2] = &[
-    // Table with field declaration
-    "((table_definition name: (identifier) @table_name (field_declaration_list (field_declaration \
-     name: (identifier) @field_name ) ))
-    (#eq? @table_name \"{}\" ) (#eq? @field_name \"{}\" ))",
-    // Relationships
-    "((table_field field: (identifier) @field_name) (#eq? @field_name \"{}\"))",
-];
-
-fn replace_rule_with_pattern(rule: &[&str], pattern: &str) -> Vec<String> {
-    rule.iter().map(|c| c.replace("{}", pattern)).collect()
-}
-
 pub fn rename(
-    source: String,
+    source: &str,
+    tree: &Tree,
+    identifiers: &IdentifiersMap,
     edit_position: LspPosition,
     new_name: String,
     file_location: Url,
 ) -> Option<WorkspaceEdit> {
-    let tree = parse_file(&source, None).unwrap();
-    let mut edits_per_document = HashMap::new();
-    let mut current_doc_changes: Vec<TextEdit> = Vec::new();
-
     let root_node = tree.root_node();
+    let (_, rule) =
+        resolve_rename(source.as_bytes(), root_node, identifiers, edit_position).ok()?;
 
-    // Retrieve current node
-    let point_conversion = Point::from(edit_position);
-    let current_node = root_node
-        .descendant_for_point_range(*point_conversion, *point_conversion)
-        .unwrap();
-
-    // we can only rename if it is an identifier or an enum in a field
-    if current_node.kind() != "identifier" {
-        return None;
-    }
-
-    let current_node_value = current_node.utf8_text(source.as_ref()).unwrap();
-    let current_node_kind = current_node.kind();
-    let wat = current_node.prev_sibling();
-    let papi = current_node.parent().unwrap();
-
-    // TODO: Set rule by finding from where we are renaming
-
-    let wat = populate_identifiers(source.as_ref(), root_node);
-
-    println!("all fields: {:?}", wat);
-    find_location_on_ast(source.as_ref(), root_node, edit_position);
-
-    // println!("rules");
-    // let oi = table_rename_rules(&source, root_node, current_node_value, &|c: Node| {
-    //     (c.utf8_text(&source).unwrap().to_string(), c.range())
-    // });
-    // println!("{:?}", oi);
-    // oi.iter().for_each(|(_name, range)| {
-    //     let edit = TextEdit {
-    //         range: Range::from(*range).0,
-    //         new_text: new_name.clone(),
-    //     };
-    //     current_doc_changes.push(edit);
-    // });
-
-    // let oi = field_rename_rules(&source, current_node_value, root_node);
-    // oi.iter().for_each(|range| {
-    //     let edit = TextEdit {
-    //         range: range.0,
-    //         new_text: new_name.clone(),
-    //     };
-    //     current_doc_changes.push(edit);
-    // });
-
-    // let mut cursor = root_node.walk();
-    // 'outer: loop {
-    // println!("Kind: {:?}", cursor.node().kind());
-    // println!("Value: {:?}", cursor.node().utf8_text(&*source));
-    // println!("field_name: {:?}", cursor.node().to_sexp());
-    //
-    // if current_node_value == cursor.node().utf8_text(&*source).unwrap() {
-    // let oi = TextEdit {
-    // range: Range::from(cursor.node().range()).0,
-    // new_text: new_name.clone(),
-    // };
-    // current_doc_changes.push(oi)
-    // }
-    //
-    // if !cursor.goto_first_child() && !cursor.goto_next_sibling() {
-    // 'inner: loop {
-    // if !cursor.goto_parent() {
-    // break 'outer;
-    // }
-    // if cursor.goto_next_sibling() {
-    // break 'inner;
-    // }
-    // }
-    // }
-    // }
+    let current_doc_changes = rule
+        .occurrences(source.as_bytes(), root_node, identifiers)
+        .into_iter()
+        .map(|range| TextEdit {
+            range: range.0,
+            new_text: new_name.clone(),
+        })
+        .collect::<Vec<TextEdit>>();
 
+    let mut edits_per_document = HashMap::new();
     edits_per_document.insert(file_location, current_doc_changes);
     Some(WorkspaceEdit {
         changes: Some(edits_per_document),
         document_changes: None,
+        change_annotations: None,
     })
 }
 
+/// Checks whether the symbol under the cursor can be renamed, returning its range and current
+/// name, or the reason why it can't be.
+pub fn prepare_rename(
+    source: &str,
+    tree: &Tree,
+    identifiers: &IdentifiersMap,
+    edit_position: LspPosition,
+) -> Result<PrepareRenameResponse, String> {
+    let (node, _) = resolve_rename(
+        source.as_bytes(),
+        tree.root_node(),
+        identifiers,
+        edit_position,
+    )?;
+
+    Ok(PrepareRenameResponse::RangeWithPlaceholder {
+        range: Range::from_tree(source.as_bytes(), node.range()).0,
+        placeholder: node.utf8_text(source.as_bytes()).unwrap().to_string(),
+    })
+}
+
+/// Retrieves the identifier under the cursor, and the rule used to rename it, or the reason why
+/// there is nothing to rename there.
+pub(crate) fn resolve_rename<'a>(
+    source: &[u8],
+    root_node: Node<'a>,
+    identifiers: &IdentifiersMap,
+    edit_position: LspPosition,
+) -> Result<(Node<'a>, RenameRules), String> {
+    let point = Point::from_lsp(source, edit_position);
+
+    // we can only rename if it is an identifier
+    let current_node = match identifier_at_point(root_node, point) {
+        Some(current_node) => current_node,
+        None => {
+            let current_node = root_node.descendant_for_point_range(*point, *point);
+            return Err(rename_rejection(source, current_node));
+        }
+    };
+
+    RenameRules::from_node(source, current_node, identifiers)
+        .map(|rule| (current_node, rule))
+        .ok_or_else(|| rename_rejection(source, Some(current_node)))
+}
+
+/// Explains why the node can't be renamed.
+fn rename_rejection(source: &[u8], node: Option<Node>) -> String {
+    let node = match node {
+        Some(node) => node,
+        None => return "There is nothing to rename here.".to_string(),
+    };
+    let node_text = node.utf8_text(source).unwrap_or_default();
+    let lowercase_text = node_text.to_lowercase();
+
+    let is_note_or_string = ["string", "note_definition"]
+        .iter()
+        .any(|kind| node.kind() == *kind || parent_of_kind(node, kind).is_some());
+    if is_note_or_string {
+        return "Strings and notes cannot be renamed.".to_string();
+    }
+
+    // Types can carry arguments, such as varchar(255)
+    let type_name = lowercase_text.split('(').next().unwrap_or_default();
+    if PRIMITIVE_TYPES.contains(&type_name) {
+        return format!("`{}` is a primitive type and cannot be renamed.", node_text);
+    }
+
+    if !node.is_named() || KEYWORDS.contains(&lowercase_text.as_str()) {
+        return format!("`{}` is a keyword and cannot be renamed.", node_text);
+    }
+
+    "Only tables, aliases, fields and enums can be renamed.".to_string()
+}
+
 /// Should resolve current node based on some rules
-enum RenameRules {
-    // Parent table: self field, indexes
-    // On every relationship
-    // Not table name
-    Field,
-    // Everytime
-    // Not field names
-    Table,
-    // Only tables, field types
-    Enum,
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum RenameRules {
+    /// Parent table: self field, indexes. On every relationship. Not table name.
+    Field { table: String, field: String },
+    /// Table name or alias, as written. Everytime, but not field names.
+    Table(String),
+    /// Only enums, field types
+    Enum(String),
 }
 
-fn table_rename_rules<T, B: AsRef<[u8]>>(
-    source: B,
-    rule: &[&str],
-    root_node: Node,
-    identifier_to_replace: &str,
-    func: &dyn Fn(Node) -> T,
-) -> Vec<T> {
-    let mut query_list: Vec<T> = vec![];
-
-    let rule_with_pattern = replace_rule_with_pattern(rule, identifier_to_replace);
-    for rule in rule_with_pattern.iter() {
-        let query = Query::new(*LANGUAGE, rule).unwrap();
-
-        let mut cursor = QueryCursor::new();
-        let captures =
-            cursor.captures(&query, root_node, |c| c.utf8_text(source.as_ref()).unwrap());
-
-        for matched in captures {
-            for capture in matched.0.captures.iter() {
-                query_list.push(func(capture.node));
+impl RenameRules {
+    /// Finds out from where we are renaming.
+    pub(crate) fn from_node(
+        source: &[u8],
+        node: Node,
+        identifiers: &IdentifiersMap,
+    ) -> Option<Self> {
+        let node_text = node.utf8_text(source).ok()?.to_string();
+        let parent = node.parent()?;
+
+        if let Some(table_field) = parent_of_kind(node, "table_field") {
+            let table = table_field.child_by_field_name("table")?;
+            if table.start_byte() == node.start_byte() {
+                return Some(RenameRules::Table(node_text));
+            }
+
+            let table_name = identifiers.table_name_of(table.utf8_text(source).ok()?);
+            return Some(RenameRules::Field {
+                table: table_name,
+                field: node_text,
+            });
+        }
+
+        // Table groups list their tables by name or alias
+        if let Some(table_group) = parent_of_kind(node, "table_group_definition") {
+            if table_group_members(table_group).contains(&node) {
+                return Some(RenameRules::Table(node_text));
+            }
+            return None;
+        }
+
+        match parent.kind() {
+            "table_definition" => return Some(RenameRules::Table(node_text)),
+            "enum_definition" => return Some(RenameRules::Enum(node_text)),
+            _ => {}
+        }
+
+        let table_name = node_parent_identifier(source, &node, "table_definition", "name")?;
+
+        if let Some(field_declaration) = parent_of_kind(node, "field_declaration") {
+            let field_name = field_declaration.child_by_field_name("name")?;
+            if field_name.start_byte() == node.start_byte() {
+                return Some(RenameRules::Field {
+                    table: table_name,
+                    field: node_text,
+                });
+            }
+
+            let is_enum = identifiers.enums_without_discriminants.contains(&node_text);
+            let is_field_type = field_type(field_declaration).map_or(false, |field_type| {
+                field_type.start_byte() == node.start_byte()
+            });
+            if is_enum && is_field_type {
+                return Some(RenameRules::Enum(node_text));
+            }
+            return None;
+        }
+
+        // Columns on indexes, but not index settings
+        let is_index_column = parent_of_kind(node, "indexes_definition").is_some()
+            && parent_of_kind(node, "index_attribute_list").is_none();
+        if is_index_column
+            && identifiers
+                .field_of_table(&table_name, &node_text)
+                .is_some()
+        {
+            return Some(RenameRules::Field {
+                table: table_name,
+                field: node_text,
+            });
+        }
+        None
+    }
+
+    /// Every range where the symbol shows up, including its definition.
+    pub(crate) fn occurrences(
+        &self,
+        source: &[u8],
+        root_node: Node,
+        identifiers: &IdentifiersMap,
+    ) -> Vec<Range> {
+        let mut ranges = self.declarations(source, root_node, identifiers);
+        ranges.extend(self.usages(source, root_node, identifiers));
+        ranges
+    }
+
+    /// Ranges where the symbol is defined.
+    pub(crate) fn declarations(
+        &self,
+        source: &[u8],
+        root_node: Node,
+        identifiers: &IdentifiersMap,
+    ) -> Vec<Range> {
+        match self {
+            RenameRules::Table(table_or_alias) => {
+                // Only the table the name stands for, even if another one has it as its alias
+                let table_name = identifiers.table_name_of(table_or_alias);
+                let field_name = if &table_name == table_or_alias {
+                    "name"
+                } else {
+                    "alias"
+                };
+
+                named_nodes(source, root_node, "table_definition", "name", &table_name)
+                    .into_iter()
+                    .filter_map(|name| name.parent()?.child_by_field_name(field_name))
+                    .filter(|name| name.utf8_text(source).ok() == Some(table_or_alias.as_str()))
+                    .map(|name| Range::from_tree(source, name.range()))
+                    .collect()
+            }
+            RenameRules::Field { table, field } => {
+                named_nodes(source, root_node, "table_definition", "name", table)
+                    .into_iter()
+                    .filter_map(|table_name| table_name.parent())
+                    .flat_map(|table| descendants_of_kind(table, "field_declaration"))
+                    .filter_map(|field_declaration| field_declaration.child_by_field_name("name"))
+                    .filter(|field_name| field_name.utf8_text(source).ok() == Some(field.as_str()))
+                    .map(|field_name| Range::from_tree(source, field_name.range()))
+                    .collect()
+            }
+            RenameRules::Enum(enum_name) => {
+                named_nodes(source, root_node, "enum_definition", "name", enum_name)
+                    .into_iter()
+                    .map(|name| Range::from_tree(source, name.range()))
+                    .collect()
             }
         }
     }
-    query_list
-}
 
-fn field_rename_rules<B: AsRef<[u8]>>(source: B, to_replace: &str, root_node: Node) -> Vec<Range> {
-    let mut query_list: Vec<_> = vec![];
-
-    let rule_with_pattern = replace_rule_with_pattern(RENAME_FIELD_RULE, to_replace);
-    println!("rules: {:#?}", rule_with_pattern);
-    for rule in rule_with_pattern.iter() {
-        let query = Query::new(*LANGUAGE, rule).unwrap();
-
-        let mut cursor = QueryCursor::new();
-        let captures =
-            cursor.captures(&query, root_node, |c| c.utf8_text(source.as_ref()).unwrap());
-
-        for matched in captures {
-            for capture in matched.0.captures.iter() {
-                let node = capture.node;
-                if node.parent().unwrap().kind() == "table_definition" {
-                    continue;
-                }
-                info!("{}", node.utf8_text(source.as_ref()).unwrap());
-                info!("{}", node.kind());
-                query_list.push(Range::from(node.range()));
+    /// Ranges where the symbol is referenced, excluding its definition.
+    pub(crate) fn usages(
+        &self,
+        source: &[u8],
+        root_node: Node,
+        identifiers: &IdentifiersMap,
+    ) -> Vec<Range> {
+        match self {
+            RenameRules::Table(table_or_alias) => {
+                let mut ranges = relationship_table_ranges(source, root_node, table_or_alias);
+                ranges.extend(table_group_member_ranges(source, root_node, table_or_alias));
+                ranges
             }
+            RenameRules::Field { table, field } => {
+                let mut ranges = relationship_field_ranges(
+                    source,
+                    root_node,
+                    &identifiers.names_of_table(table),
+                    field,
+                );
+                ranges.extend(index_field_ranges(source, root_node, table, field));
+                ranges
+            }
+            RenameRules::Enum(enum_name) => enum_typed_field_ranges(source, root_node, enum_name),
         }
     }
-    query_list
+}
+
+/// Name nodes, under the given field, of every node of the kind whose name is the given one.
+fn named_nodes<'a>(
+    source: &[u8],
+    root_node: Node<'a>,
+    kind: &str,
+    field_name: &str,
+    name: &str,
+) -> Vec<Node<'a>> {
+    descendants_of_kind(root_node, kind)
+        .into_iter()
+        .filter_map(|node| node.child_by_field_name(field_name))
+        .filter(|name_node| name_node.utf8_text(source).ok() == Some(name))
+        .collect()
+}
+
+/// Tables written on relationships, either standalone or inline.
+fn relationship_table_ranges(source: &[u8], root_node: Node, table_or_alias: &str) -> Vec<Range> {
+    named_nodes(source, root_node, "table_field", "table", table_or_alias)
+        .into_iter()
+        .map(|table| Range::from_tree(source, table.range()))
+        .collect()
+}
+
+/// Tables listed as members of table groups.
+fn table_group_member_ranges(source: &[u8], root_node: Node, table_or_alias: &str) -> Vec<Range> {
+    descendants_of_kind(root_node, "table_group_definition")
+        .into_iter()
+        .flat_map(table_group_members)
+        .filter(|member| member.utf8_text(source).ok() == Some(table_or_alias))
+        .map(|member| Range::from_tree(source, member.range()))
+        .collect()
+}
+
+/// Identifiers of the table group, except for its own name.
+fn table_group_members(table_group: Node) -> Vec<Node> {
+    let group_name = table_group.child_by_field_name("name");
+
+    descendants_of_kind(table_group, "identifier")
+        .into_iter()
+        .filter(|identifier| group_name != Some(*identifier))
+        .collect()
+}
+
+/// Field on every relationship, either standalone or inline, pointing to any of the table names.
+fn relationship_field_ranges(
+    source: &[u8],
+    root_node: Node,
+    table_names: &[String],
+    field_name: &str,
+) -> Vec<Range> {
+    descendants_of_kind(root_node, "table_field")
+        .into_iter()
+        .filter(|table_field| {
+            table_field
+                .child_by_field_name("table")
+                .and_then(|table| table.utf8_text(source).ok())
+                .map_or(false, |table| table_names.iter().any(|name| name == table))
+        })
+        .flat_map(table_field_columns)
+        .filter(|column| column.utf8_text(source).ok() == Some(field_name))
+        .map(|column| Range::from_tree(source, column.range()))
+        .collect()
+}
+
+/// Field used as a column on the indexes of its own table.
+fn index_field_ranges(
+    source: &[u8],
+    root_node: Node,
+    table_name: &str,
+    field_name: &str,
+) -> Vec<Range> {
+    descendants_of_kind(root_node, "table_definition")
+        .into_iter()
+        .filter(|table| {
+            table
+                .child_by_field_name("name")
+                .and_then(|name| name.utf8_text(source).ok())
+                == Some(table_name)
+        })
+        .flat_map(|table| descendants_of_kind(table, "indexes_definition"))
+        .flat_map(|indexes| descendants_of_kind(indexes, "identifier"))
+        .filter(|column| parent_of_kind(*column, "index_attribute_list").is_none())
+        .filter(|column| column.utf8_text(source).ok() == Some(field_name))
+        .map(|column| Range::from_tree(source, column.range()))
+        .collect()
+}
+
+/// Fields whose type is the enum.
+fn enum_typed_field_ranges(source: &[u8], root_node: Node, enum_name: &str) -> Vec<Range> {
+    descendants_of_kind(root_node, "field_declaration")
+        .into_iter()
+        .filter_map(field_type)
+        .filter(|field_type| field_type.utf8_text(source).ok() == Some(enum_name))
+        .map(|field_type| Range::from_tree(source, field_type.range()))
+        .collect()
 }
@@ -0,0 +1,227 @@
+use tower_lsp::lsp_types::{
+    Range as LspRange, SemanticToken, SemanticTokenModifier, SemanticTokenType,
+    SemanticTokensLegend,
+};
+use tree_sitter::{Node, Tree};
+
+use super::{rename::RenameRules, validation::unresolved_references};
+use crate::{
+    document::byte_offset,
+    navigation::{descendants_of_kind, enum_values, field_type},
+    wrappers::Range,
+    IdentifiersMap,
+};
+
+/// Kinds of tokens told apart by the server, in the order of the legend.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum TokenKind {
+    Table,
+    Alias,
+    Column,
+    Enum,
+    EnumMember,
+    Type,
+    SettingKeyword,
+    Operator,
+    Note,
+}
+
+const TOKEN_TYPES: &[SemanticTokenType] = &[
+    SemanticTokenType::STRUCT,
+    SemanticTokenType::VARIABLE,
+    SemanticTokenType::PROPERTY,
+    SemanticTokenType::ENUM,
+    SemanticTokenType::ENUM_MEMBER,
+    SemanticTokenType::TYPE,
+    SemanticTokenType::KEYWORD,
+    SemanticTokenType::OPERATOR,
+    SemanticTokenType::STRING,
+];
+
+/// Set on tables, columns and enums which are referenced but never defined.
+const UNRESOLVED: SemanticTokenModifier = SemanticTokenModifier::new("unresolved");
+const UNRESOLVED_BIT: u32 = 1;
+
+/// Token kinds and modifiers, announced on initialization.
+pub fn semantic_tokens_legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: TOKEN_TYPES.to_vec(),
+        token_modifiers: vec![UNRESOLVED],
+    }
+}
+
+/// A token spanning a single line, delimited by byte offsets.
+#[derive(Debug, Clone, Copy)]
+struct Token {
+    start: usize,
+    end: usize,
+    kind: TokenKind,
+    unresolved: bool,
+}
+
+/// Classifies the identifiers, types, settings, operators and notes of the document, restricted to
+/// the given range if any.
+pub fn semantic_tokens(
+    source: &str,
+    tree: &Tree,
+    identifiers: &IdentifiersMap,
+    range: Option<LspRange>,
+) -> Vec<SemanticToken> {
+    let root_node = tree.root_node();
+    let unresolved = unresolved_references(source.as_bytes(), root_node, identifiers);
+
+    let mut tokens = vec![];
+    let mut push = |node: Node, kind| {
+        let is_unresolved = unresolved
+            .iter()
+            .any(|reference| reference.range == Range::from_tree(source.as_bytes(), node.range()));
+        push_token(
+            &mut tokens,
+            source,
+            node.start_byte(),
+            node.end_byte(),
+            kind,
+            is_unresolved,
+        );
+    };
+
+    for identifier in descendants_of_kind(root_node, "identifier") {
+        let kind = match RenameRules::from_node(source.as_bytes(), identifier, identifiers) {
+            Some(RenameRules::Table(name)) if identifiers.table_name_of(&name) == name => {
+                TokenKind::Table
+            }
+            Some(RenameRules::Table(_)) => TokenKind::Alias,
+            Some(RenameRules::Field { .. }) => TokenKind::Column,
+            Some(RenameRules::Enum(_)) => TokenKind::Enum,
+            None => continue,
+        };
+        push(identifier, kind);
+    }
+
+    for enum_definition in descendants_of_kind(root_node, "enum_definition") {
+        for value in enum_values(enum_definition) {
+            push(value, TokenKind::EnumMember);
+        }
+    }
+
+    // Enum types are already classified along the identifiers
+    let field_types = descendants_of_kind(root_node, "field_declaration")
+        .into_iter()
+        .filter_map(field_type);
+    for field_type in field_types {
+        let type_name = field_type.utf8_text(source.as_bytes()).unwrap_or_default();
+        if !identifiers
+            .enums_without_discriminants
+            .iter()
+            .any(|enum_name| enum_name == type_name)
+        {
+            push(field_type, TokenKind::Type);
+        }
+    }
+
+    for operator in descendants_of_kind(root_node, "cardinality_op") {
+        push(operator, TokenKind::Operator);
+    }
+
+    for note in descendants_of_kind(root_node, "note_definition") {
+        for string in descendants_of_kind(note, "string") {
+            push(string, TokenKind::Note);
+        }
+    }
+
+    let attribute_lists = descendants_of_kind(root_node, "field_attribute_list")
+        .into_iter()
+        .chain(descendants_of_kind(root_node, "index_attribute_list"));
+    for attributes in attribute_lists {
+        for setting in
+            (0..attributes.named_child_count()).filter_map(|idx| attributes.named_child(idx))
+        {
+            let text = setting.utf8_text(source.as_bytes()).unwrap_or_default();
+            // Settings are either a keyword, as in `pk`, or a keyword and a value, as in `note: ''`
+            let keyword = text.split(':').next().unwrap_or_default().trim();
+            if keyword.is_empty() {
+                continue;
+            }
+            let start = setting.start_byte() + text.find(keyword).unwrap_or_default();
+            push_token(
+                &mut tokens,
+                source,
+                start,
+                start + keyword.len(),
+                TokenKind::SettingKeyword,
+                false,
+            );
+        }
+    }
+
+    tokens.sort_by_key(|token| token.start);
+    let mut last_end = 0;
+    tokens.retain(|token| {
+        let overlaps = token.start < last_end;
+        if !overlaps {
+            last_end = token.end;
+        }
+        !overlaps
+    });
+
+    if let Some(range) = range {
+        let (range_start, range_end) = (
+            byte_offset(source, range.start),
+            byte_offset(source, range.end),
+        );
+        tokens.retain(|token| token.end > range_start && token.start < range_end);
+    }
+
+    encode(source, &tokens)
+}
+
+/// Pushes the token, split on each line it spans, since clients may not support multiline tokens.
+fn push_token(
+    tokens: &mut Vec<Token>,
+    source: &str,
+    start: usize,
+    end: usize,
+    kind: TokenKind,
+    unresolved: bool,
+) {
+    let mut line_start = start;
+    for line in source[start..end].split('\n') {
+        if !line.is_empty() {
+            tokens.push(Token {
+                start: line_start,
+                end: line_start + line.len(),
+                kind,
+                unresolved,
+            });
+        }
+        line_start += line.len() + 1;
+    }
+}
+
+/// Encodes each token relative to the previous one, with columns counted in UTF-16 code units.
+fn encode(source: &str, tokens: &[Token]) -> Vec<SemanticToken> {
+    let mut encoded = Vec::with_capacity(tokens.len());
+    let (mut previous_line, mut previous_start) = (0, 0);
+
+    for token in tokens {
+        let before = &source[..token.start];
+        let line = before.matches('\n').count() as u32;
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        let start = source[line_start..token.start].encode_utf16().count() as u32;
+
+        encoded.push(SemanticToken {
+            delta_line: line - previous_line,
+            delta_start: if line == previous_line {
+                start - previous_start
+            } else {
+                start
+            },
+            length: source[token.start..token.end].encode_utf16().count() as u32,
+            token_type: token.kind as u32,
+            token_modifiers_bitset: if token.unresolved { UNRESOLVED_BIT } else { 0 },
+        });
+        previous_line = line;
+        previous_start = start;
+    }
+    encoded
+}
@@ -0,0 +1,365 @@
+use tower_lsp::lsp_types::Diagnostic;
+use tree_sitter::{Node, Tree};
+use url::Url;
+
+use super::{
+    compatibility::{compatibility_diagnostics, TypeCompatibility},
+    completion::PRIMITIVE_TYPES,
+    diagnostics::error_diagnostic,
+    duplicates::duplicate_diagnostics,
+    indexes::index_diagnostics,
+    relationship_settings::relationship_setting_diagnostics,
+};
+use crate::{
+    navigation::{descendants_of_kind, field_type, parent_of_kind, table_field_columns},
+    wrappers::Range,
+    IdentifiersMap,
+};
+
+/// Common SQL types, accepted as column types on top of enums.
+const SQL_TYPES: &[&str] = &[
+    "bigint",
+    "bigserial",
+    "binary",
+    "bit",
+    "blob",
+    "bool",
+    "boolean",
+    "bytea",
+    "char",
+    "character",
+    "cidr",
+    "date",
+    "datetime",
+    "datetime2",
+    "decimal",
+    "double",
+    "float4",
+    "float8",
+    "inet",
+    "int2",
+    "int4",
+    "int8",
+    "integer",
+    "interval",
+    "json",
+    "jsonb",
+    "longtext",
+    "macaddr",
+    "mediumint",
+    "mediumtext",
+    "money",
+    "nchar",
+    "numeric",
+    "nvarchar",
+    "real",
+    "serial",
+    "smallint",
+    "smallserial",
+    "string",
+    "time",
+    "timestamp",
+    "timestamptz",
+    "timetz",
+    "tinyint",
+    "tinytext",
+    "uuid",
+    "varbinary",
+    "xml",
+];
+
+/// Most suggestions offered on a single diagnostic.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// What kind of identifier could not be resolved.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum Unresolved {
+    Table,
+    /// Column of the given table, as written on the reference
+    Field(String),
+    Enum,
+}
+
+/// An identifier that points to nothing defined on the document.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct UnresolvedReference {
+    pub(crate) kind: Unresolved,
+    pub(crate) name: String,
+    pub(crate) range: Range,
+    /// Closest existing identifiers, best match first
+    pub(crate) suggestions: Vec<String>,
+}
+
+impl UnresolvedReference {
+    pub(crate) fn to_diagnostic(&self) -> Diagnostic {
+        let message = match &self.kind {
+            Unresolved::Table => format!("unknown table or alias `{}`", self.name),
+            Unresolved::Field(table) => {
+                format!("unknown column `{}` on table `{}`", self.name, table)
+            }
+            Unresolved::Enum => format!("unknown enum `{}`", self.name),
+        };
+
+        error_diagnostic(self.range, with_suggestions(message, &self.suggestions))
+    }
+}
+
+/// Appends the suggestions to the message, if there are any.
+pub(crate) fn with_suggestions(mut message: String, suggestions: &[String]) -> String {
+    if !suggestions.is_empty() {
+        let suggestions = suggestions
+            .iter()
+            .map(|suggestion| format!("`{}`", suggestion))
+            .collect::<Vec<_>>()
+            .join(", ");
+        message.push_str(&format!(", did you mean {}?", suggestions));
+    }
+    message
+}
+
+/// Reports every table, column and enum referenced but never defined, identifiers defined more
+/// than once, misused indexes, invalid relationship settings, and relationships between
+/// incompatible columns.
+pub fn semantic_diagnostics(
+    source: &str,
+    tree: &Tree,
+    identifiers: &IdentifiersMap,
+    compatibility: &TypeCompatibility,
+    file_location: &Url,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = unresolved_references(source.as_bytes(), tree.root_node(), identifiers)
+        .iter()
+        .map(UnresolvedReference::to_diagnostic)
+        .collect::<Vec<_>>();
+    diagnostics.extend(duplicate_diagnostics(
+        source.as_bytes(),
+        tree,
+        file_location,
+    ));
+    diagnostics.extend(index_diagnostics(source.as_bytes(), tree, file_location));
+    diagnostics.extend(relationship_setting_diagnostics(
+        source.as_bytes(),
+        tree,
+        identifiers,
+    ));
+    diagnostics.extend(compatibility_diagnostics(
+        source.as_bytes(),
+        tree,
+        identifiers,
+        compatibility,
+        file_location,
+    ));
+    diagnostics
+}
+
+pub(crate) fn unresolved_references(
+    source: &[u8],
+    root_node: Node,
+    identifiers: &IdentifiersMap,
+) -> Vec<UnresolvedReference> {
+    let mut unresolved = unresolved_table_fields(source, root_node, identifiers);
+    unresolved.extend(unresolved_index_columns(source, root_node, identifiers));
+    unresolved.extend(unresolved_enums(source, root_node, identifiers));
+    unresolved
+}
+
+/// Columns of indexes missing from their own table. Expressions between backticks are not
+/// checked.
+fn unresolved_index_columns(
+    source: &[u8],
+    root_node: Node,
+    identifiers: &IdentifiersMap,
+) -> Vec<UnresolvedReference> {
+    let mut unresolved = vec![];
+
+    for table in descendants_of_kind(root_node, "table_definition") {
+        let table_name = match table
+            .child_by_field_name("name")
+            .and_then(|name| name.utf8_text(source).ok())
+        {
+            Some(table_name) => table_name,
+            None => continue,
+        };
+        let field_names = identifiers
+            .fields_of_table(table_name)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|field| field.text_name)
+            .collect::<Vec<_>>();
+
+        let columns = descendants_of_kind(table, "index_declaration")
+            .into_iter()
+            .flat_map(|index| {
+                descendants_of_kind(index, "identifier")
+                    .into_iter()
+                    .filter(move |column| {
+                        // An odd number of backticks before the column means it is on an expression
+                        let backticks = source[index.start_byte()..column.start_byte()]
+                            .iter()
+                            .filter(|byte| **byte == b'`')
+                            .count();
+                        backticks % 2 == 0
+                    })
+            })
+            .filter(|column| parent_of_kind(*column, "index_attribute_list").is_none());
+        for column in columns {
+            let column_name = column.utf8_text(source).unwrap();
+            if field_names.iter().any(|field| field == column_name) {
+                continue;
+            }
+
+            unresolved.push(UnresolvedReference {
+                kind: Unresolved::Field(table_name.to_string()),
+                name: column_name.to_string(),
+                range: Range::from_tree(source, column.range()),
+                suggestions: suggestions(column_name, &field_names),
+            });
+        }
+    }
+    unresolved
+}
+
+/// Tables and columns on relationships, either standalone or inline.
+fn unresolved_table_fields(
+    source: &[u8],
+    root_node: Node,
+    identifiers: &IdentifiersMap,
+) -> Vec<UnresolvedReference> {
+    let mut unresolved = vec![];
+    let table_names = identifiers
+        .table_definitions
+        .keys()
+        .cloned()
+        .collect::<Vec<_>>();
+
+    for table_field in descendants_of_kind(root_node, "table_field") {
+        let table = match table_field.child_by_field_name("table") {
+            Some(table) => table,
+            None => continue,
+        };
+        let table_name = table.utf8_text(source).unwrap();
+
+        if identifiers.table_definition(table_name).is_none() {
+            unresolved.push(UnresolvedReference {
+                kind: Unresolved::Table,
+                name: table_name.to_string(),
+                range: Range::from_tree(source, table.range()),
+                suggestions: suggestions(table_name, &table_names),
+            });
+            continue;
+        }
+
+        let field_names = identifiers
+            .fields_of_table(table_name)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|field| field.text_name)
+            .collect::<Vec<_>>();
+
+        for column in table_field_columns(table_field) {
+            let column_name = column.utf8_text(source).unwrap();
+            if field_names.iter().any(|field| field == column_name) {
+                continue;
+            }
+
+            unresolved.push(UnresolvedReference {
+                kind: Unresolved::Field(table_name.to_string()),
+                name: column_name.to_string(),
+                range: Range::from_tree(source, column.range()),
+                suggestions: suggestions(column_name, &field_names),
+            });
+        }
+    }
+    unresolved
+}
+
+/// Column types that are neither SQL types nor enums defined on the document, but are close
+/// enough to an enum to be a typo. DBML takes any type as is, so other unknown types are no error.
+fn unresolved_enums(
+    source: &[u8],
+    root_node: Node,
+    identifiers: &IdentifiersMap,
+) -> Vec<UnresolvedReference> {
+    descendants_of_kind(root_node, "field_declaration")
+        .into_iter()
+        .filter_map(field_type)
+        .filter_map(|field_type| {
+            let type_text = field_type.utf8_text(source).unwrap();
+            if is_known_type(type_text, identifiers) {
+                return None;
+            }
+
+            let suggestions = suggestions(type_text, &identifiers.enums_without_discriminants);
+            if suggestions.is_empty() {
+                return None;
+            }
+
+            Some(UnresolvedReference {
+                kind: Unresolved::Enum,
+                name: type_text.to_string(),
+                range: Range::from_tree(source, field_type.range()),
+                suggestions,
+            })
+        })
+        .collect()
+}
+
+fn is_known_type(type_text: &str, identifiers: &IdentifiersMap) -> bool {
+    // Quoted types, such as "double precision", are taken as is
+    if type_text.contains(|c: char| c == '"' || c.is_whitespace()) {
+        return true;
+    }
+    if identifiers
+        .enums_without_discriminants
+        .iter()
+        .any(|enum_name| enum_name == type_text)
+    {
+        return true;
+    }
+
+    // Types can carry arguments or be arrays, such as varchar(255) or int[]
+    let base_type = type_text
+        .split(|c: char| c == '(' || c == '[')
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    PRIMITIVE_TYPES.contains(&base_type.as_str()) || SQL_TYPES.contains(&base_type.as_str())
+}
+
+/// Candidates close enough to the name to be a likely typo, closest first.
+pub(crate) fn suggestions(name: &str, candidates: &[String]) -> Vec<String> {
+    let max_distance = (name.chars().count() / 3).max(1);
+
+    let mut close_candidates = candidates
+        .iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect::<Vec<_>>();
+    close_candidates.sort();
+    close_candidates.dedup();
+
+    close_candidates
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate.to_string())
+        .collect()
+}
+
+/// Levenshtein distance between both strings.
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let b_chars = b.chars().collect::<Vec<_>>();
+    let mut previous_row = (0..=b_chars.len()).collect::<Vec<_>>();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current_row = vec![i + 1];
+
+        for (j, b_char) in b_chars.iter().enumerate() {
+            let substitution = previous_row[j] + if a_char == *b_char { 0 } else { 1 };
+            let insertion = current_row[j] + 1;
+            let deletion = previous_row[j + 1] + 1;
+            current_row.push(substitution.min(insertion).min(deletion));
+        }
+        previous_row = current_row;
+    }
+    previous_row[b_chars.len()]
+}
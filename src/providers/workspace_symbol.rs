@@ -0,0 +1,119 @@
+use tower_lsp::lsp_types::{Location, SymbolInformation, SymbolKind};
+use url::Url;
+
+use crate::{
+    file::open_and_parse_with_source, populate_identifiers, wrappers::Range, IdentifiersMap,
+};
+
+/// Lists the tables, columns as `table.column`, and enums of a document.
+#[allow(deprecated)]
+pub fn document_workspace_symbols(
+    identifiers: &IdentifiersMap,
+    file_location: &Url,
+) -> Vec<SymbolInformation> {
+    let symbol = |name: String, kind, range: Range, container: Option<&str>| SymbolInformation {
+        name,
+        kind,
+        tags: None,
+        deprecated: None,
+        location: Location::new(file_location.clone(), range.0),
+        container_name: container.map(|container| container.to_string()),
+    };
+    let mut symbols = vec![];
+
+    // Aliases also point to their table, which is listed only once
+    let tables = identifiers
+        .table_definitions
+        .iter()
+        .filter(|(name, table)| **name == table.text_name);
+    for (table_name, table) in tables {
+        symbols.push(symbol(
+            table_name.clone(),
+            SymbolKind::Struct,
+            table.range,
+            None,
+        ));
+
+        for field in identifiers.fields_of_table(table_name).unwrap_or_default() {
+            let name = format!("{}.{}", table_name, field.text_name);
+            symbols.push(symbol(
+                name,
+                SymbolKind::Field,
+                field.range,
+                Some(table_name.as_str()),
+            ));
+        }
+    }
+
+    for (enum_name, enum_info) in identifiers.enum_definitions.iter() {
+        symbols.push(symbol(
+            enum_name.clone(),
+            SymbolKind::Enum,
+            enum_info.range,
+            None,
+        ));
+    }
+    symbols
+}
+
+/// Indexes a document which is not open, straight from disk.
+pub fn file_workspace_symbols(file_location: &Url) -> Vec<SymbolInformation> {
+    match open_and_parse_with_source(file_location, None) {
+        Ok((Some(tree), source)) => {
+            let identifiers = populate_identifiers(&source, tree.root_node());
+            document_workspace_symbols(&identifiers, file_location)
+        }
+        _ => vec![],
+    }
+}
+
+/// Keeps only the symbols matching the query, best matches first.
+pub fn rank_workspace_symbols(
+    query: &str,
+    symbols: Vec<SymbolInformation>,
+) -> Vec<SymbolInformation> {
+    let query = query.to_lowercase();
+
+    let mut ranked = symbols
+        .into_iter()
+        .filter_map(|symbol| Some((match_score(&query, &symbol.name)?, symbol)))
+        .collect::<Vec<_>>();
+    ranked.sort_by(|(a_score, a), (b_score, b)| {
+        a_score
+            .cmp(b_score)
+            .then_with(|| a.name.len().cmp(&b.name.len()))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+
+    ranked.into_iter().map(|(_, symbol)| symbol).collect()
+}
+
+/// Scores how well the candidate matches the lowercase query, lower being better. Exact matches
+/// come first, then prefixes, substrings, and at last, characters found in order with gaps.
+fn match_score(query: &str, candidate: &str) -> Option<usize> {
+    let candidate = candidate.to_lowercase();
+
+    if query.is_empty() || candidate == query {
+        return Some(0);
+    }
+    if candidate.starts_with(query) {
+        return Some(1);
+    }
+    if let Some(position) = candidate.find(query) {
+        return Some(2 + position);
+    }
+
+    // Fuzzy match, penalized by how spread apart the characters are
+    let mut gaps = 0;
+    let mut candidate_chars = candidate.chars();
+    for query_char in query.chars() {
+        loop {
+            match candidate_chars.next() {
+                Some(c) if c == query_char => break,
+                Some(_) => gaps += 1,
+                None => return None,
+            }
+        }
+    }
+    Some(100 + gaps)
+}
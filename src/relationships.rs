@@ -0,0 +1,148 @@
+//! Relationships are written either standalone, as in `Ref: orders.user_id > users.id`, or inline
+//! on the column settings, as in `user_id int [ref: > users.id]`.
+//!
+//! Both forms are gathered here with the same shape, so providers don't need to tell them apart.
+
+use tower_lsp::lsp_types::Range as LspRange;
+use tree_sitter::Node;
+
+use crate::{
+    lexer::{code_bytes, split_code},
+    navigation::{
+        descendants_of_kind, node_parent_identifier, parent_of_kind, table_field_columns,
+    },
+    wrappers::{lsp_position, Range},
+};
+
+/// Cardinality operators, longest first so `<>` is not taken as `<`.
+pub(crate) const CARDINALITY_OPERATORS: &[&str] = &["<>", ">", "<", "-"];
+
+#[derive(Debug, Clone)]
+pub(crate) struct Relationship<'a> {
+    pub(crate) left: RelationshipSide<'a>,
+    /// The `cardinality_op` node, such as `>`
+    pub(crate) operator: Node<'a>,
+    pub(crate) right: RelationshipSide<'a>,
+    /// Field declaration holding the relationship, if it is an inline one
+    pub(crate) inline_on: Option<Node<'a>>,
+}
+
+impl<'a> Relationship<'a> {
+    /// Settings of a standalone relationship, as in `[delete: cascade]`, each along with its
+    /// range, or `None` if it has no settings list. The list must start on the same line.
+    pub(crate) fn settings(&self, source: &[u8]) -> Option<Vec<(Range, String)>> {
+        let right = self.right.table_field?;
+        if self.inline_on.is_some() {
+            return None;
+        }
+
+        let line_rest = &source[right.end_byte()..];
+        let line_end = line_rest
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .unwrap_or(line_rest.len());
+        let line_rest = std::str::from_utf8(&line_rest[..line_end]).ok()?;
+
+        let list_start = line_rest.len() - line_rest.trim_start().len();
+        if !line_rest[list_start..].starts_with('[') {
+            return None;
+        }
+        let list_end = code_bytes(&line_rest[list_start..])
+            .into_iter()
+            .find(|(_, byte)| *byte == b']')
+            .map_or(line_rest.len(), |(list_end, _)| list_start + list_end);
+
+        let mut setting_start = right.end_byte() + list_start + 1;
+        let mut settings = vec![];
+
+        // Quoted values, as in `color: '#fff'`, may hold commas
+        for setting in split_code(&line_rest[list_start + 1..list_end], b',') {
+            let text = setting.trim();
+            if !text.is_empty() {
+                let start = setting_start + setting.len() - setting.trim_start().len();
+                settings.push((
+                    Range(LspRange::new(
+                        lsp_position(source, start),
+                        lsp_position(source, start + text.len()),
+                    )),
+                    text.to_string(),
+                ));
+            }
+            setting_start += setting.len() + 1;
+        }
+        Some(settings)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct RelationshipSide<'a> {
+    /// Table name or alias, as written
+    pub(crate) table: String,
+    pub(crate) columns: Vec<Node<'a>>,
+    /// The `table_field` node, which inline relationships don't have on their left side
+    pub(crate) table_field: Option<Node<'a>>,
+}
+
+impl<'a> RelationshipSide<'a> {
+    fn from_table_field(source: &[u8], table_field: Node<'a>) -> Option<Self> {
+        let table = table_field.child_by_field_name("table")?;
+
+        Some(RelationshipSide {
+            table: table.utf8_text(source).ok()?.to_string(),
+            columns: table_field_columns(table_field),
+            table_field: Some(table_field),
+        })
+    }
+
+    /// The column on which an inline relationship is set.
+    fn from_field_declaration(source: &[u8], field_declaration: Node<'a>) -> Option<Self> {
+        let field_name = field_declaration.child_by_field_name("name")?;
+
+        Some(RelationshipSide {
+            table: node_parent_identifier(source, &field_name, "table_definition", "name")?,
+            columns: vec![field_name],
+            table_field: None,
+        })
+    }
+
+    pub(crate) fn column_names(&self, source: &'a [u8]) -> Vec<&'a str> {
+        self.columns
+            .iter()
+            .filter_map(|column| column.utf8_text(source).ok())
+            .collect()
+    }
+}
+
+/// Gathers every relationship of the document, starting from its cardinality operator.
+pub(crate) fn relationships<'a>(source: &[u8], root_node: Node<'a>) -> Vec<Relationship<'a>> {
+    descendants_of_kind(root_node, "cardinality_op")
+        .into_iter()
+        .filter_map(|operator| relationship_of_operator(source, operator))
+        .collect()
+}
+
+fn relationship_of_operator<'a>(source: &[u8], operator: Node<'a>) -> Option<Relationship<'a>> {
+    let right_table_field = operator.next_named_sibling()?;
+    if right_table_field.kind() != "table_field" {
+        return None;
+    }
+    let right = RelationshipSide::from_table_field(source, right_table_field)?;
+
+    match operator.prev_named_sibling() {
+        Some(left_table_field) if left_table_field.kind() == "table_field" => Some(Relationship {
+            left: RelationshipSide::from_table_field(source, left_table_field)?,
+            operator,
+            right,
+            inline_on: None,
+        }),
+        _ => {
+            let field_declaration = parent_of_kind(operator, "field_declaration")?;
+            Some(Relationship {
+                left: RelationshipSide::from_field_declaration(source, field_declaration)?,
+                operator,
+                right,
+                inline_on: Some(field_declaration),
+            })
+        }
+    }
+}
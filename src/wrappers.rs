@@ -4,6 +4,8 @@ use std::ops::{Deref, DerefMut};
 use tower_lsp::lsp_types::{Position as LspPosition, Range as LspRange};
 use tree_sitter::{Point as TreePoint, Range as TreeRange};
 
+use crate::document::{byte_offset, tree_point};
+
 #[derive(Debug, Clone, Copy)]
 pub struct Point(pub TreePoint);
 #[derive(Debug, Clone, Copy)]
@@ -11,34 +13,51 @@ pub struct Position(pub LspPosition);
 #[derive(Default, Debug, Copy, Clone, Eq, PartialEq)]
 pub struct Range(pub LspRange);
 
-impl From<TreeRange> for Range {
-    fn from(range: TreeRange) -> Self {
+impl Range {
+    /// Converts the range of a node, whose columns are counted in bytes, into a LSP range, whose
+    /// characters are counted in UTF-16 code units.
+    pub fn from_tree(source: &[u8], range: TreeRange) -> Self {
         Self {
             0: LspRange {
-                start: LspPosition {
-                    line: range.start_point.row as u64,
-                    character: range.start_point.column as u64,
-                },
-                end: LspPosition {
-                    line: range.end_point.row as u64,
-                    character: range.end_point.column as u64,
-                },
+                start: utf16_position(source, range.start_byte, range.start_point),
+                end: utf16_position(source, range.end_byte, range.end_point),
             },
         }
     }
 }
 
-impl From<LspPosition> for Point {
-    fn from(pos: LspPosition) -> Self {
-        Self {
-            0: TreePoint {
-                row: pos.line as usize,
-                column: pos.character as usize,
-            },
-        }
+/// Converts a byte offset into a LSP position, whose character is counted in UTF-16 code units.
+pub fn lsp_position(source: &[u8], byte_offset: usize) -> LspPosition {
+    let byte_offset = byte_offset.min(source.len());
+    let point = tree_point(&String::from_utf8_lossy(source), byte_offset);
+    utf16_position(source, byte_offset, point)
+}
+
+/// Counts the column of a point in UTF-16 code units, from the bytes preceding it on its line.
+fn utf16_position(source: &[u8], byte_offset: usize, point: TreePoint) -> LspPosition {
+    let line_start = byte_offset.saturating_sub(point.column);
+    let character = source
+        .get(line_start..byte_offset)
+        .map_or(point.column, |before| {
+            String::from_utf8_lossy(before).encode_utf16().count()
+        });
+
+    LspPosition {
+        line: point.row as u32,
+        character: character as u32,
     }
 }
+
 impl Point {
+    /// Converts a LSP position, whose character is counted in UTF-16 code units, into a point,
+    /// whose column is counted in bytes.
+    pub fn from_lsp(source: &[u8], position: LspPosition) -> Self {
+        let source = String::from_utf8_lossy(source);
+        Self {
+            0: tree_point(&source, byte_offset(&source, position)),
+        }
+    }
+
     pub fn column_start(&self) -> Point {
         Self {
             0: TreePoint {